@@ -3,35 +3,43 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 fn size_2(c: &mut Criterion) {
     let image = image::open("bench_data/red-maze.png").unwrap().to_rgb8();
-    let pattern_set = wfc::get_patterns(&image, 2);
+    let pattern_set = wfc::get_patterns(&wfc::Texture::from_image(&image), 2, true);
     let patterns = pattern_set.iter().collect();
     let solver = wfc::Wfc::new(patterns);
 
     let mut group = c.benchmark_group("sample-size-10");
     group.sample_size(10);
     group.bench_function("32x32-with-p-size-2", |b| {
-        b.iter(|| solver.generate(black_box(32), black_box(32)))
+        b.iter(|| {
+            solver
+                .generate(black_box(32), black_box(32), black_box(0))
+                .unwrap()
+        })
     });
     group.finish();
 }
 
 fn size_3(c: &mut Criterion) {
     let image = image::open("bench_data/water.png").unwrap().to_rgb8();
-    let pattern_set = wfc::get_patterns(&image, 3);
+    let pattern_set = wfc::get_patterns(&wfc::Texture::from_image(&image), 3, true);
     let patterns = pattern_set.iter().collect();
     let solver = wfc::Wfc::new(patterns);
 
     let mut group = c.benchmark_group("sample-size-10");
     group.sample_size(10);
     group.bench_function("32x32 with p-size 3", |b| {
-        b.iter(|| solver.generate(black_box(32), black_box(32)))
+        b.iter(|| {
+            solver
+                .generate(black_box(32), black_box(32), black_box(0))
+                .unwrap()
+        })
     });
     group.finish();
 }
 
 fn build_constraints(c: &mut Criterion) {
     let image = image::open("bench_data/red-maze.png").unwrap().to_rgb8();
-    let pattern_set = wfc::get_patterns(&image, 2);
+    let pattern_set = wfc::get_patterns(&wfc::Texture::from_image(&image), 2, true);
     let patterns = pattern_set.iter().collect();
 
     let mut group = c.benchmark_group("sample-size-100");
@@ -41,7 +49,7 @@ fn build_constraints(c: &mut Criterion) {
     });
 
     let image = image::open("bench_data/water.png").unwrap().to_rgb8();
-    let pattern_set = wfc::get_patterns(&image, 3);
+    let pattern_set = wfc::get_patterns(&wfc::Texture::from_image(&image), 3, true);
     let patterns = pattern_set.iter().collect();
     group.bench_function("build-constraints-water-size-3", |b| {
         b.iter(|| wfc::Wfc::build_constraints(&patterns))
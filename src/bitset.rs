@@ -0,0 +1,208 @@
+use std::fmt::Debug;
+
+/// The amount of bits stored in each word of a `BitSet`.
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A fixed-size set of small integers, stored one bit per integer.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    words: Box<[u64]>,
+}
+
+impl BitSet {
+    /// Creates an empty set that can hold the integers in `0..len`.
+    pub fn new(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(WORD_BITS)].into_boxed_slice(),
+        }
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words[i / WORD_BITS] >> (i % WORD_BITS) & 1 != 0
+    }
+
+    /// Adds `i` to the set, returning whether it wasn't already there.
+    pub fn insert(&mut self, i: usize) -> bool {
+        let word = &mut self.words[i / WORD_BITS];
+        let mask = 1 << (i % WORD_BITS);
+        let inserted = *word & mask == 0;
+        *word |= mask;
+        inserted
+    }
+
+    /// The amount of integers in the set.
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Iterates over the integers in the set, in increasing order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            words: self.words.iter(),
+            base: 0,
+            word: 0,
+        }
+    }
+
+    /// Adds every integer in `other` to the set.
+    pub fn union_with(&mut self, other: &BitSet) {
+        for (word, other) in self.words.iter_mut().zip(other.words.iter()) {
+            *word |= other;
+        }
+    }
+}
+
+/// Many `BitSet`s of the same size, stored one after the other so that
+/// they don't each need their own allocation.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BitSets {
+    /// The amount of words each set takes.
+    stride: usize,
+    words: Vec<u64>,
+}
+
+impl BitSets {
+    /// Creates `sets` copies of `set`.
+    pub fn repeat(set: &BitSet, sets: usize) -> Self {
+        BitSets {
+            stride: set.words.len(),
+            words: set.words.repeat(sets),
+        }
+    }
+
+    /// The word holding `i` in the set at `set`, and the mask of its bit.
+    fn locate(&self, set: usize, i: usize) -> (usize, u64) {
+        (set * self.stride + i / WORD_BITS, 1 << (i % WORD_BITS))
+    }
+
+    pub fn contains(&self, set: usize, i: usize) -> bool {
+        let (word, mask) = self.locate(set, i);
+        self.words[word] & mask != 0
+    }
+
+    /// Adds `i` to the set at `set`, returning whether it wasn't already
+    /// there.
+    pub fn insert(&mut self, set: usize, i: usize) -> bool {
+        let (word, mask) = self.locate(set, i);
+        let inserted = self.words[word] & mask == 0;
+        self.words[word] |= mask;
+        inserted
+    }
+
+    /// Removes `i` from the set at `set`, returning whether it was there.
+    pub fn remove(&mut self, set: usize, i: usize) -> bool {
+        let (word, mask) = self.locate(set, i);
+        let removed = self.words[word] & mask != 0;
+        self.words[word] &= !mask;
+        removed
+    }
+
+    /// Iterates over the integers in the set at `set`, in increasing order.
+    pub fn iter(&self, set: usize) -> Iter<'_> {
+        Iter {
+            words: self.words[set * self.stride..(set + 1) * self.stride].iter(),
+            base: 0,
+            word: 0,
+        }
+    }
+}
+
+/// An iterator over the integers in a `BitSet`, in increasing order.
+pub struct Iter<'a> {
+    words: std::slice::Iter<'a, u64>,
+    /// The first integer held by the word after `word`.
+    base: usize,
+    /// The bits of the current word that haven't been yielded yet.
+    word: u64,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            self.word = *self.words.next()?;
+            self.base += WORD_BITS;
+        }
+
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.base - WORD_BITS + bit)
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    /// Collects the integers into a set just big enough to hold the
+    /// largest of them.
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let items: Vec<usize> = iter.into_iter().collect();
+        let len = items.iter().max().map_or(0, |&max| max + 1);
+        let mut set = BitSet::new(len);
+        for i in items {
+            set.insert(i);
+        }
+
+        set
+    }
+}
+
+impl Debug for BitSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{BitSet, BitSets};
+
+    #[test]
+    fn insert() {
+        let mut set = BitSet::new(130);
+        assert_eq!(set.count(), 0);
+        assert!(set.insert(0));
+        assert!(set.insert(64));
+        assert!(set.insert(129));
+        assert!(!set.insert(64));
+        assert_eq!(set.count(), 3);
+        assert!(set.contains(129));
+        assert!(!set.contains(128));
+        assert_eq!(set.iter().collect_vec(), vec![0, 64, 129]);
+    }
+
+    #[test]
+    fn union_with() {
+        let mut set: BitSet = [1, 64].into_iter().collect();
+        let other: BitSet = [2, 64].into_iter().collect();
+        set.union_with(&other);
+        assert_eq!(set.iter().collect_vec(), vec![1, 2, 64]);
+    }
+
+    #[test]
+    fn from_iter() {
+        let set: BitSet = [5, 1, 3].into_iter().collect();
+        assert_eq!(set.count(), 3);
+        assert_eq!(set.iter().collect_vec(), vec![1, 3, 5]);
+        assert!(!set.contains(4));
+
+        let set: BitSet = (0..70).collect();
+        assert_eq!(set.count(), 70);
+        assert_eq!(set.iter().collect_vec(), (0..70).collect_vec());
+    }
+
+    #[test]
+    fn bit_sets() {
+        let set: BitSet = [1, 64, 65].into_iter().collect();
+        let mut sets = BitSets::repeat(&set, 3);
+        assert!(sets.remove(1, 64));
+        assert!(!sets.remove(1, 64));
+        assert!(sets.insert(2, 0));
+        assert!(!sets.contains(1, 64));
+        assert!(sets.contains(0, 64));
+        assert_eq!(sets.iter(0).collect_vec(), vec![1, 64, 65]);
+        assert_eq!(sets.iter(1).collect_vec(), vec![1, 65]);
+        assert_eq!(sets.iter(2).collect_vec(), vec![0, 1, 64, 65]);
+    }
+}
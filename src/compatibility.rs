@@ -0,0 +1,359 @@
+use std::hash::Hash;
+
+use itertools::Itertools;
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::bitset::{self, BitSet};
+
+/// The largest amount of patterns stored densely.
+///
+/// A dense table takes `N² / 2` bytes, so this caps it at 8 MiB.
+pub const DENSE_LIMIT: usize = 4096;
+
+/// The amount of patterns each task looks up the overlapping patterns of,
+/// when building a table in parallel.
+#[cfg(feature = "parallel")]
+pub(crate) const OVERLAPPING_CHUNK_SIZE: usize = 64;
+
+/// Which patterns can be put next to each other, in each direction.
+///
+/// Pattern `p2` is compatible with `p1` in direction `d` if `p2` can be put
+/// next to `p1`, on its `d` side. Directions are anything that converts to
+/// an index, usually a [`Direction`](crate::Direction).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compatibility {
+    directions: usize,
+    repr: Repr,
+}
+
+/// How the compatible patterns of each pattern and direction are stored,
+/// indexed by `p1 * directions + d`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Repr {
+    /// One bit-row per pattern and direction.
+    Dense(Vec<BitSet>),
+    /// The ids of the compatible patterns, in increasing order.
+    Sparse(Vec<Vec<usize>>),
+}
+
+impl Compatibility {
+    /// Creates a table for the patterns with ids in `0..len` and the four
+    /// planar directions, where no pattern is compatible with any other.
+    ///
+    /// The table is dense unless there are more than [`DENSE_LIMIT`]
+    /// patterns.
+    pub fn new(len: usize) -> Self {
+        Compatibility::with_directions(len, 4)
+    }
+
+    /// Like [`Compatibility::new`], but for the given amount of directions.
+    pub fn with_directions(len: usize, directions: usize) -> Self {
+        if len <= DENSE_LIMIT {
+            Compatibility::dense(len, directions)
+        } else {
+            Compatibility::sparse(len, directions)
+        }
+    }
+
+    /// Creates a dense table, which stores a bit for every pair of patterns
+    /// in each direction.
+    ///
+    /// Lookups are fastest on dense tables, but they take `N²` bits per
+    /// direction, so prefer [`Compatibility::with_directions`] unless the
+    /// amount of patterns is known to be small.
+    pub fn dense(len: usize, directions: usize) -> Self {
+        assert!(directions > 0, "a table needs at least one direction");
+        Compatibility {
+            directions,
+            repr: Repr::Dense(vec![BitSet::new(len); len * directions]),
+        }
+    }
+
+    /// Creates a sparse table, which stores the list of compatible patterns
+    /// of every pattern in each direction.
+    ///
+    /// Sparse tables take memory in proportion to the amount of compatible
+    /// pairs, so they suit large sets of patterns with few neighbors each.
+    pub fn sparse(len: usize, directions: usize) -> Self {
+        assert!(directions > 0, "a table needs at least one direction");
+        Compatibility {
+            directions,
+            repr: Repr::Sparse(vec![Vec::new(); len * directions]),
+        }
+    }
+
+    /// Builds the table of overlapping patterns, where `p2` is compatible
+    /// with `p1` in a direction when the `side` of `p1` facing it is the
+    /// `side` of `p2` facing the `opposite` way.
+    ///
+    /// Patterns come with their ids, in increasing order, and the table
+    /// holds the ids up to the largest one. Patterns are bucketed by their
+    /// sides, so that only the patterns that overlap are compared. The table
+    /// has a direction for each of `directions`, which must be in the order
+    /// of their indices.
+    pub(crate) fn overlapping<'p, P: 'p, S: Eq + Hash, D: Copy + Into<usize>>(
+        patterns: impl IntoIterator<Item = (usize, &'p P)>,
+        directions: &[D],
+        opposite: impl Fn(D) -> D,
+        side: impl Fn(&'p P, D) -> S,
+    ) -> Self {
+        let patterns = patterns.into_iter().collect_vec();
+        let buckets = side_buckets(&patterns, directions, opposite, &side);
+        let rows = patterns
+            .iter()
+            .map(|&(_, pattern)| overlapping_row(&buckets, directions, pattern, &side))
+            .collect();
+        Compatibility::from_rows(&patterns, directions, rows)
+    }
+
+    /// Like [`Compatibility::overlapping`], but looks up the overlapping
+    /// patterns of each chunk of patterns in parallel.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn par_overlapping<'p, P, S, D>(
+        patterns: impl IntoIterator<Item = (usize, &'p P)>,
+        directions: &[D],
+        opposite: impl Fn(D) -> D,
+        side: impl Fn(&'p P, D) -> S + Sync,
+    ) -> Self
+    where
+        P: Sync + 'p,
+        S: Eq + Hash + Send + Sync,
+        D: Copy + Into<usize> + Sync,
+    {
+        use rayon::prelude::*;
+
+        let patterns = patterns.into_iter().collect_vec();
+        let buckets = side_buckets(&patterns, directions, opposite, &side);
+        let rows = patterns
+            .par_chunks(OVERLAPPING_CHUNK_SIZE)
+            .flat_map_iter(|chunk| {
+                chunk
+                    .iter()
+                    .map(|&(_, pattern)| overlapping_row(&buckets, directions, pattern, &side))
+            })
+            .collect();
+        Compatibility::from_rows(&patterns, directions, rows)
+    }
+
+    /// Builds the table from the ids of the patterns each pattern overlaps
+    /// in each direction, given in the order of `patterns`.
+    fn from_rows<P, D: Copy + Into<usize>>(
+        patterns: &[(usize, P)],
+        directions: &[D],
+        rows: Vec<Vec<&[usize]>>,
+    ) -> Self {
+        let len = patterns.last().map_or(0, |&(id, _)| id + 1);
+        let mut compatibility = Compatibility::with_directions(len, directions.len());
+        for (&(p1, _), row) in patterns.iter().zip(rows) {
+            for (&d, compatible) in directions.iter().zip(row) {
+                for &p2 in compatible {
+                    compatibility.insert(p1, p2, d);
+                }
+            }
+        }
+
+        compatibility
+    }
+
+    /// The amount of directions in the table.
+    pub fn directions(&self) -> usize {
+        self.directions
+    }
+
+    /// The amount of patterns in the table.
+    pub fn len(&self) -> usize {
+        let rows = match &self.repr {
+            Repr::Dense(rows) => rows.len(),
+            Repr::Sparse(lists) => lists.len(),
+        };
+        rows / self.directions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Makes `p2` compatible with `p1` in the given direction.
+    ///
+    /// Sparse tables expect `p2` to be inserted in increasing order for
+    /// each `p1` and direction.
+    pub fn insert(&mut self, p1: usize, p2: usize, direction: impl Into<usize>) {
+        let row = p1 * self.directions + direction.into();
+        match &mut self.repr {
+            Repr::Dense(rows) => {
+                rows[row].insert(p2);
+            }
+            Repr::Sparse(lists) => {
+                let list = &mut lists[row];
+                debug_assert!(list.last().is_none_or(|&last| last < p2));
+                list.push(p2);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contains(&self, p1: usize, p2: usize, direction: impl Into<usize>) -> bool {
+        let row = p1 * self.directions + direction.into();
+        match &self.repr {
+            Repr::Dense(rows) => rows[row].contains(p2),
+            Repr::Sparse(lists) => lists[row].binary_search(&p2).is_ok(),
+        }
+    }
+
+    /// Whether the table stores the list of compatible patterns of each
+    /// pattern, rather than a bit for every pair.
+    #[cfg(test)]
+    pub(crate) fn is_sparse(&self) -> bool {
+        matches!(self.repr, Repr::Sparse(_))
+    }
+
+    /// Iterates over the patterns compatible with `p1` in the given
+    /// direction, in increasing order.
+    pub fn compatible(
+        &self,
+        p1: usize,
+        direction: impl Into<usize>,
+    ) -> impl Iterator<Item = usize> + '_ {
+        let row = p1 * self.directions + direction.into();
+        match &self.repr {
+            Repr::Dense(rows) => Compatible::Dense(rows[row].iter()),
+            Repr::Sparse(lists) => Compatible::Sparse(lists[row].iter()),
+        }
+    }
+
+    /// Returns the patterns that can be put next to any of the `possible`
+    /// patterns in the given direction.
+    pub(crate) fn allowed(
+        &self,
+        possible: impl IntoIterator<Item = usize>,
+        direction: impl Into<usize>,
+    ) -> BitSet {
+        let d = direction.into();
+        let mut allowed = BitSet::new(self.len());
+        match &self.repr {
+            Repr::Dense(rows) => {
+                for p1 in possible {
+                    allowed.union_with(&rows[p1 * self.directions + d]);
+                }
+            }
+            Repr::Sparse(lists) => {
+                for p1 in possible {
+                    for &p2 in &lists[p1 * self.directions + d] {
+                        allowed.insert(p2);
+                    }
+                }
+            }
+        }
+
+        allowed
+    }
+}
+
+/// An iterator over the patterns compatible with a pattern.
+enum Compatible<'c> {
+    Dense(bitset::Iter<'c>),
+    Sparse(std::slice::Iter<'c, usize>),
+}
+
+impl Iterator for Compatible<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Compatible::Dense(iter) => iter.next(),
+            Compatible::Sparse(iter) => iter.next().copied(),
+        }
+    }
+}
+
+/// Buckets the ids of the patterns by the side they show to a pattern on
+/// their `opposite` side, in each direction.
+///
+/// The ids in each bucket are in the order of `patterns`, which sparse
+/// tables need to be increasing.
+fn side_buckets<'p, P, S: Eq + Hash, D: Copy>(
+    patterns: &[(usize, &'p P)],
+    directions: &[D],
+    opposite: impl Fn(D) -> D,
+    side: impl Fn(&'p P, D) -> S,
+) -> Vec<HashMap<S, Vec<usize>>> {
+    directions
+        .iter()
+        .map(|&d| {
+            let mut bucket: HashMap<S, Vec<usize>> = HashMap::default();
+            for &(id, pattern) in patterns {
+                bucket
+                    .entry(side(pattern, opposite(d)))
+                    .or_default()
+                    .push(id);
+            }
+            bucket
+        })
+        .collect()
+}
+
+/// The ids of the patterns `pattern` overlaps, in each direction.
+fn overlapping_row<'b, 'p, P, S: Eq + Hash, D: Copy>(
+    buckets: &'b [HashMap<S, Vec<usize>>],
+    directions: &[D],
+    pattern: &'p P,
+    side: impl Fn(&'p P, D) -> S,
+) -> Vec<&'b [usize]> {
+    directions
+        .iter()
+        .zip(buckets)
+        .map(|(&d, bucket)| bucket.get(&side(pattern, d)).map_or(&[][..], Vec::as_slice))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::Compatibility;
+    use crate::bitset::BitSet;
+    use crate::direction::Direction;
+
+    #[test]
+    fn dense_and_sparse() {
+        for mut table in [Compatibility::dense(70, 4), Compatibility::sparse(70, 4)] {
+            table.insert(0, 1, Direction::Up);
+            table.insert(0, 65, Direction::Up);
+            table.insert(0, 2, Direction::Left);
+            table.insert(3, 0, Direction::Up);
+
+            assert_eq!(table.len(), 70);
+            assert!(table.contains(0, 1, Direction::Up));
+            assert!(table.contains(0, 65, Direction::Up));
+            assert!(!table.contains(0, 2, Direction::Up));
+            assert!(!table.contains(1, 0, Direction::Up));
+            assert!(table.contains(0, 2, Direction::Left));
+
+            assert_eq!(
+                table.compatible(0, Direction::Up).collect_vec(),
+                vec![1, 65]
+            );
+            assert_eq!(table.compatible(0, Direction::Down).count(), 0);
+
+            let possible: BitSet = [0, 3].into_iter().collect();
+            let allowed = table.allowed(possible.iter(), Direction::Up);
+            assert_eq!(allowed.iter().collect_vec(), vec![0, 1, 65]);
+        }
+    }
+
+    #[test]
+    fn with_directions() {
+        let mut table = Compatibility::with_directions(3, 6);
+        assert_eq!(table.directions(), 6);
+        table.insert(0, 1, 5usize);
+        assert!(table.contains(0, 1, 5usize));
+        assert!(!table.contains(0, 1, 4usize));
+        assert_eq!(Compatibility::new(3).directions(), 4);
+    }
+
+    #[test]
+    fn new() {
+        assert!(!Compatibility::new(2).is_sparse());
+        assert!(Compatibility::new(super::DENSE_LIMIT + 1).is_sparse());
+    }
+}
@@ -0,0 +1,434 @@
+use crate::compatibility::Compatibility;
+use crate::direction::{transpose, Direction};
+use crate::texture::Texture;
+use crate::topology::Topology;
+
+/// A face of a cube, named as seen from the outside with the `y` axis up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    /// The face towards `+z`.
+    Front,
+    /// The face towards `+x`.
+    Right,
+    /// The face towards `-z`.
+    Back,
+    /// The face towards `-x`.
+    Left,
+    /// The face towards `+y`.
+    Top,
+    /// The face towards `-y`.
+    Bottom,
+}
+
+impl Face {
+    pub fn all() -> [Face; 6] {
+        [
+            Face::Front,
+            Face::Right,
+            Face::Back,
+            Face::Left,
+            Face::Top,
+            Face::Bottom,
+        ]
+    }
+
+    /// The outward normal of the face, and the axes along which its `x`
+    /// and `y` coordinates grow.
+    ///
+    /// The four side faces keep `y` growing downwards, while the top and
+    /// bottom faces meet the front face without turning, as in the cross
+    /// layout.
+    fn axes(self) -> [[i32; 3]; 3] {
+        match self {
+            Face::Front => [[0, 0, 1], [1, 0, 0], [0, -1, 0]],
+            Face::Right => [[1, 0, 0], [0, 0, -1], [0, -1, 0]],
+            Face::Back => [[0, 0, -1], [-1, 0, 0], [0, -1, 0]],
+            Face::Left => [[-1, 0, 0], [0, 0, 1], [0, -1, 0]],
+            Face::Top => [[0, 1, 0], [1, 0, 0], [0, 0, 1]],
+            Face::Bottom => [[0, -1, 0], [1, 0, 0], [0, 0, -1]],
+        }
+    }
+
+    /// The position of the face in the cross layout, in faces.
+    fn cross_pos(self) -> (usize, usize) {
+        match self {
+            Face::Front => (1, 1),
+            Face::Right => (2, 1),
+            Face::Back => (3, 1),
+            Face::Left => (0, 1),
+            Face::Top => (1, 0),
+            Face::Bottom => (1, 2),
+        }
+    }
+}
+
+impl From<Face> for usize {
+    fn from(value: Face) -> Self {
+        match value {
+            Face::Front => 0,
+            Face::Right => 1,
+            Face::Back => 2,
+            Face::Left => 3,
+            Face::Top => 4,
+            Face::Bottom => 5,
+        }
+    }
+}
+
+impl From<usize> for Face {
+    fn from(value: usize) -> Self {
+        match value {
+            0 => Face::Front,
+            1 => Face::Right,
+            2 => Face::Back,
+            3 => Face::Left,
+            4 => Face::Top,
+            5 => Face::Bottom,
+            _ => panic!("Invalid face"),
+        }
+    }
+}
+
+/// The surface of a cube, made of six `size`x`size` grids whose borders are
+/// joined along the edges of the cube.
+///
+/// Crossing some of the edges turns the grid: walking up from the right
+/// face leads onto the top face walking left. Edges are labeled
+/// `4 * turns + d`, where `d` is the [`Direction`] of the edge in the face
+/// it leaves, as seen in the output, and `turns` is the amount of quarter
+/// turns counterclockwise that the face it leads to is drawn with, when
+/// unfolded next to the face it leaves.
+///
+/// Cells are stored face by face, in the order of [`Face::all`], and row by
+/// row within a face.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CubeSphere {
+    size: usize,
+}
+
+impl CubeSphere {
+    pub fn new(size: usize) -> Self {
+        CubeSphere { size }
+    }
+
+    /// The width and height of each face.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn idx_to_pos(&self, idx: usize) -> (Face, (usize, usize)) {
+        let area = self.size * self.size;
+        let face = Face::from(idx / area);
+        let idx = idx % area;
+        (face, (idx % self.size, idx / self.size))
+    }
+
+    pub fn pos_to_idx(&self, face: Face, (x, y): (usize, usize)) -> usize {
+        (usize::from(face) * self.size + y) * self.size + x
+    }
+
+    /// The position of the center of a cell, in a space where the cube
+    /// spans `-size..=size` along each axis.
+    fn center(&self, face: Face, (x, y): (usize, usize)) -> [i32; 3] {
+        let [normal, u, v] = face.axes();
+        let size = self.size as i32;
+        let (x, y) = (2 * x as i32 + 1 - size, 2 * y as i32 + 1 - size);
+        [0, 1, 2].map(|i| size * normal[i] + x * u[i] + y * v[i])
+    }
+}
+
+impl Topology for CubeSphere {
+    fn len(&self) -> usize {
+        6 * self.size * self.size
+    }
+
+    fn directions(&self) -> usize {
+        16
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        let (face, (x, y)) = self.idx_to_pos(idx);
+        let [normal, u, v] = face.axes();
+        let mut neighbors = Vec::with_capacity(4);
+
+        for d in Direction::all() {
+            let (dx, dy) = match d {
+                Direction::Up => (0, -1),
+                Direction::Right => (1, 0),
+                Direction::Down => (0, 1),
+                Direction::Left => (-1, 0),
+            };
+            let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+            let size = self.size as i32;
+            if (0..size).contains(&nx) && (0..size).contains(&ny) {
+                let neighbor = self.pos_to_idx(face, (nx as usize, ny as usize));
+                neighbors.push((usize::from(d), neighbor));
+                continue;
+            }
+
+            // Stepping over the edge folds the step onto the face the step
+            // points to, so the neighbor lies half a cell further along the
+            // step and half a cell into the cube.
+            let step = [0, 1, 2].map(|i| dx * u[i] + dy * v[i]);
+            let other = Face::all()
+                .into_iter()
+                .find(|f| f.axes()[0] == step)
+                .unwrap();
+            let center = self.center(face, (x, y));
+            let center = [0, 1, 2].map(|i| center[i] + step[i] - normal[i]);
+            let [_, other_u, other_v] = other.axes();
+            let coordinate = |axis: [i32; 3]| (dot(center, axis) + size - 1) as usize / 2;
+            let neighbor = self.pos_to_idx(other, (coordinate(other_u), coordinate(other_v)));
+
+            // Unfolding the other face around the edge turns its normal
+            // into ours and ours into the opposite of the step, which shows
+            // where its `x` axis lies in our face.
+            let edge = cross(normal, step);
+            let unfolded =
+                [0, 1, 2].map(|i| -dot(other_u, normal) * step[i] + dot(other_u, edge) * edge[i]);
+            let turns = match (dot(unfolded, u), dot(unfolded, v)) {
+                (1, 0) => 0,
+                (0, -1) => 1,
+                (-1, 0) => 2,
+                (0, 1) => 3,
+                _ => unreachable!("the faces should be oriented alike"),
+            };
+
+            neighbors.push((4 * turns + usize::from(d), neighbor));
+        }
+
+        neighbors
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        // Walking back is walking the opposite way, turned back into the
+        // frame of the other face.
+        let (turns, d) = (direction / 4, direction % 4);
+        let back = (usize::from(Direction::from(d).opposite()) + turns) % 4;
+        4 * ((4 - turns) % 4) + back
+    }
+}
+
+fn dot(a: [i32; 3], b: [i32; 3]) -> i32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [i32; 3], b: [i32; 3]) -> [i32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Extends the constraints table of an overlapping model to the edges of a
+/// [`CubeSphere`].
+///
+/// `rotated[id]` is the id of the pattern turned a quarter counterclockwise,
+/// if the model has it. A pattern can only be put across a turned edge if
+/// the model has it turned the same way.
+pub(crate) fn compatibility(planar: &Compatibility, rotated: &[Option<usize>]) -> Compatibility {
+    let len = planar.len();
+
+    // The pattern each pattern is drawn as after each amount of turns, and
+    // the pattern drawn as each pattern after them.
+    let mut turned = vec![(0..len).map(Some).collect::<Vec<_>>()];
+    for turns in 1..4 {
+        let previous: &Vec<Option<usize>> = &turned[turns - 1];
+        turned.push(previous.iter().map(|id| rotated[(*id)?]).collect());
+    }
+    let mut unturned = vec![vec![None; len]; 4];
+    for (turns, turned) in turned.iter().enumerate() {
+        for (id, to) in turned.iter().enumerate() {
+            if let Some(to) = *to {
+                unturned[turns][to] = Some(id);
+            }
+        }
+    }
+
+    // A pattern fits across an edge turned `turns` times when it shows a
+    // compatible pattern once turned.
+    let mut compatibility = Compatibility::with_directions(len, 16);
+    for p1 in 0..len {
+        for (turns, unturned) in unturned.iter().enumerate() {
+            for d in Direction::all() {
+                let mut compatible = planar
+                    .compatible(p1, transpose(d))
+                    .filter_map(|p2| unturned[p2])
+                    .collect::<Vec<_>>();
+                compatible.sort_unstable();
+                for p2 in compatible {
+                    compatibility.insert(p1, p2, 4 * turns + usize::from(d));
+                }
+            }
+        }
+    }
+
+    compatibility
+}
+
+/// The six faces of a cube, generated as a single output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CubeMap<T> {
+    /// The faces, in the order of [`Face::all`].
+    faces: Vec<Texture<T>>,
+}
+
+impl<T: Copy> CubeMap<T> {
+    pub(crate) fn new(faces: Vec<Texture<T>>) -> Self {
+        assert_eq!(faces.len(), 6);
+        CubeMap { faces }
+    }
+
+    /// The width and height of each face.
+    pub fn size(&self) -> usize {
+        self.faces[0].width()
+    }
+
+    pub fn face(&self, face: Face) -> &Texture<T> {
+        &self.faces[usize::from(face)]
+    }
+
+    /// Lays the faces out as a cross, four faces wide and three faces tall:
+    ///
+    /// ```text
+    ///       top
+    /// left  front  right  back
+    ///       bottom
+    /// ```
+    ///
+    /// The corners of the atlas are filled with `background`.
+    pub fn to_cross(&self, background: T) -> Texture<T> {
+        let size = self.size();
+        let mut atlas = Texture::from_fn(4 * size, 3 * size, |_, _| background);
+        for face in Face::all() {
+            let (fx, fy) = face.cross_pos();
+            for (x, y) in (0..size).flat_map(|x| (0..size).map(move |y| (x, y))) {
+                atlas[(fx * size + x, fy * size + y)] = self.face(face)[(x, y)];
+            }
+        }
+
+        atlas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{CubeSphere, Face};
+    use crate::compatibility::Compatibility;
+    use crate::direction::Direction;
+    use crate::error::WfcError;
+    use crate::topology::Topology;
+    use crate::wfc::{Graph, Solver};
+
+    #[test]
+    fn cube_sphere() {
+        let cube = CubeSphere::new(3);
+        assert_eq!(cube.len(), 54);
+        for idx in 0..cube.len() {
+            let (face, pos) = cube.idx_to_pos(idx);
+            assert_eq!(cube.pos_to_idx(face, pos), idx);
+            assert_eq!(cube.neighbors(idx).len(), 4);
+        }
+
+        // Every edge leads back with the opposite label.
+        Graph::new(&cube);
+
+        // The front face meets the others without turning.
+        let label = |d: Direction, turns: usize| 4 * turns + usize::from(d);
+        let front = |pos| cube.pos_to_idx(Face::Front, pos);
+        assert_eq!(
+            cube.neighbors(front((0, 0))),
+            vec![
+                (label(Direction::Up, 0), cube.pos_to_idx(Face::Top, (0, 2))),
+                (label(Direction::Right, 0), front((1, 0))),
+                (label(Direction::Down, 0), front((0, 1))),
+                (
+                    label(Direction::Left, 0),
+                    cube.pos_to_idx(Face::Left, (2, 0))
+                ),
+            ]
+        );
+
+        // Walking up from the right face leads onto the top face walking
+        // left, whose `x` axis points down when unfolded above the right
+        // face.
+        let right = cube.pos_to_idx(Face::Right, (0, 0));
+        let top = cube.pos_to_idx(Face::Top, (2, 2));
+        assert!(cube
+            .neighbors(right)
+            .contains(&(label(Direction::Up, 3), top)));
+        assert!(cube
+            .neighbors(top)
+            .contains(&(label(Direction::Right, 1), right)));
+        assert_eq!(
+            cube.opposite(label(Direction::Up, 3)),
+            label(Direction::Right, 1)
+        );
+
+        // The back face is upside down next to the top face.
+        let back = cube.pos_to_idx(Face::Back, (0, 0));
+        assert!(cube
+            .neighbors(back)
+            .contains(&(label(Direction::Up, 2), cube.pos_to_idx(Face::Top, (2, 0)))));
+    }
+
+    #[test]
+    fn compatibility() {
+        // Pattern `0` turns into `1` and back, while pattern `2` has no
+        // turned version. In the output frame, `1` can be put right of `0`
+        // and `2` above `1`.
+        let mut planar = Compatibility::new(3);
+        planar.insert(0, 1, super::transpose(Direction::Right));
+        planar.insert(1, 2, super::transpose(Direction::Up));
+        let rotated = [Some(1), Some(0), None];
+        let compatibility = super::compatibility(&planar, &rotated);
+        assert_eq!(compatibility.directions(), 16);
+
+        let compatible = |p1, d: Direction, turns: usize| {
+            compatibility
+                .compatible(p1, 4 * turns + usize::from(d))
+                .collect_vec()
+        };
+        assert_eq!(compatible(0, Direction::Right, 0), vec![1]);
+        assert_eq!(compatible(0, Direction::Right, 1), vec![0]);
+        assert_eq!(compatible(0, Direction::Right, 2), vec![1]);
+        assert_eq!(compatible(1, Direction::Up, 0), vec![2]);
+        assert!(compatible(1, Direction::Up, 1).is_empty());
+        assert!(compatible(0, Direction::Left, 0).is_empty());
+    }
+
+    #[test]
+    fn solve() {
+        // Three cells meet at each corner of the cube, so two colors can't
+        // tell every pair of neighbors apart, but three can.
+        let different = |len: usize| {
+            let mut compatibility = Compatibility::with_directions(len, 16);
+            for (p1, p2) in (0..len).cartesian_product(0..len) {
+                for d in 0..16usize {
+                    if p1 != p2 {
+                        compatibility.insert(p1, p2, d);
+                    }
+                }
+            }
+            compatibility
+        };
+
+        let cube = CubeSphere::new(4);
+        let ids = Solver::new(different(3), vec![1.0; 3])
+            .solve(&cube, 0)
+            .unwrap();
+        for idx in 0..cube.len() {
+            for (_, neighbor) in cube.neighbors(idx) {
+                assert_ne!(ids[idx], ids[neighbor]);
+            }
+        }
+
+        assert!(matches!(
+            Solver::new(different(2), vec![1.0; 2]).solve(&cube, 0),
+            Err(WfcError::NodeContradiction { .. } | WfcError::NodeBacktrackLimitReached { .. })
+        ));
+    }
+}
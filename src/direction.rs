@@ -1,3 +1,8 @@
+/// A direction on a planar grid, as used by the constraints tables.
+///
+/// Rows of the tables are columns of the output, so `Up` points to the
+/// previous column and `Left` to the previous row, as [`Direction::add_pos`]
+/// shows. See [`Grid`](crate::Grid).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
     Up,
@@ -42,6 +47,20 @@ impl Direction {
     }
 }
 
+/// Turns a direction in the output into the direction the solver uses for
+/// it.
+///
+/// Rows of the solver's table are columns of the output, so up and left
+/// are swapped, and so are down and right.
+pub(crate) fn transpose(direction: Direction) -> Direction {
+    match direction {
+        Direction::Up => Direction::Left,
+        Direction::Right => Direction::Down,
+        Direction::Down => Direction::Right,
+        Direction::Left => Direction::Up,
+    }
+}
+
 impl From<(i32, i32)> for Direction {
     fn from(value: (i32, i32)) -> Self {
         match value {
@@ -112,6 +131,242 @@ impl From<Direction> for u8 {
     }
 }
 
+/// A direction along a sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction1 {
+    /// Towards the previous element.
+    Previous,
+    /// Towards the next element.
+    Next,
+}
+
+impl Direction1 {
+    pub fn all() -> [Direction1; 2] {
+        [Direction1::Previous, Direction1::Next]
+    }
+
+    pub fn opposite(&self) -> Direction1 {
+        match self {
+            Direction1::Previous => Direction1::Next,
+            Direction1::Next => Direction1::Previous,
+        }
+    }
+
+    /// The offset to the neighboring element in this direction.
+    pub fn offset(&self) -> i32 {
+        match self {
+            Direction1::Previous => -1,
+            Direction1::Next => 1,
+        }
+    }
+}
+
+impl From<usize> for Direction1 {
+    fn from(value: usize) -> Self {
+        match value {
+            0 => Direction1::Previous,
+            1 => Direction1::Next,
+            _ => panic!("Invalid direction"),
+        }
+    }
+}
+
+impl From<Direction1> for usize {
+    fn from(value: Direction1) -> Self {
+        match value {
+            Direction1::Previous => 0,
+            Direction1::Next => 1,
+        }
+    }
+}
+
+/// A direction in a grid of voxels.
+///
+/// The `z` axis points up, like in MagicaVoxel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction3 {
+    /// Towards `x - 1`.
+    Left,
+    /// Towards `x + 1`.
+    Right,
+    /// Towards `y - 1`.
+    Front,
+    /// Towards `y + 1`.
+    Back,
+    /// Towards `z - 1`.
+    Down,
+    /// Towards `z + 1`.
+    Up,
+}
+
+impl Direction3 {
+    pub fn all() -> [Direction3; 6] {
+        [
+            Direction3::Left,
+            Direction3::Right,
+            Direction3::Front,
+            Direction3::Back,
+            Direction3::Down,
+            Direction3::Up,
+        ]
+    }
+
+    pub fn opposite(&self) -> Direction3 {
+        match self {
+            Direction3::Left => Direction3::Right,
+            Direction3::Right => Direction3::Left,
+            Direction3::Front => Direction3::Back,
+            Direction3::Back => Direction3::Front,
+            Direction3::Down => Direction3::Up,
+            Direction3::Up => Direction3::Down,
+        }
+    }
+
+    /// The offset to the neighboring voxel in this direction.
+    pub fn offset(&self) -> (i32, i32, i32) {
+        match self {
+            Direction3::Left => (-1, 0, 0),
+            Direction3::Right => (1, 0, 0),
+            Direction3::Front => (0, -1, 0),
+            Direction3::Back => (0, 1, 0),
+            Direction3::Down => (0, 0, -1),
+            Direction3::Up => (0, 0, 1),
+        }
+    }
+}
+
+impl From<usize> for Direction3 {
+    fn from(value: usize) -> Self {
+        match value {
+            0 => Direction3::Left,
+            1 => Direction3::Right,
+            2 => Direction3::Front,
+            3 => Direction3::Back,
+            4 => Direction3::Down,
+            5 => Direction3::Up,
+            _ => panic!("Invalid direction"),
+        }
+    }
+}
+
+impl From<Direction3> for usize {
+    fn from(value: Direction3) -> Self {
+        match value {
+            Direction3::Left => 0,
+            Direction3::Right => 1,
+            Direction3::Front => 2,
+            Direction3::Back => 3,
+            Direction3::Down => 4,
+            Direction3::Up => 5,
+        }
+    }
+}
+
+/// A direction in a hexagonal grid, in axial coordinates `(q, r)`.
+///
+/// Rows of the grid have the same `r`, and each one is shifted half a cell
+/// to the right of the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HexDirection {
+    /// Towards `(q + 1, r)`.
+    East,
+    /// Towards `(q + 1, r - 1)`.
+    NorthEast,
+    /// Towards `(q, r - 1)`.
+    NorthWest,
+    /// Towards `(q - 1, r)`.
+    West,
+    /// Towards `(q - 1, r + 1)`.
+    SouthWest,
+    /// Towards `(q, r + 1)`.
+    SouthEast,
+}
+
+impl HexDirection {
+    /// The six directions, counterclockwise from the east.
+    pub fn all() -> [HexDirection; 6] {
+        [
+            HexDirection::East,
+            HexDirection::NorthEast,
+            HexDirection::NorthWest,
+            HexDirection::West,
+            HexDirection::SouthWest,
+            HexDirection::SouthEast,
+        ]
+    }
+
+    pub fn opposite(&self) -> HexDirection {
+        match self {
+            HexDirection::East => HexDirection::West,
+            HexDirection::NorthEast => HexDirection::SouthWest,
+            HexDirection::NorthWest => HexDirection::SouthEast,
+            HexDirection::West => HexDirection::East,
+            HexDirection::SouthWest => HexDirection::NorthEast,
+            HexDirection::SouthEast => HexDirection::NorthWest,
+        }
+    }
+
+    pub fn add_pos(&self, (q, r): (i32, i32)) -> (i32, i32) {
+        let (dq, dr) = self.offset();
+        (q + dq, r + dr)
+    }
+
+    /// The offset to the neighboring cell in this direction.
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            HexDirection::East => (1, 0),
+            HexDirection::NorthEast => (1, -1),
+            HexDirection::NorthWest => (0, -1),
+            HexDirection::West => (-1, 0),
+            HexDirection::SouthWest => (-1, 1),
+            HexDirection::SouthEast => (0, 1),
+        }
+    }
+
+    pub fn from_neighbors((q, r): (usize, usize), (nq, nr): (usize, usize)) -> HexDirection {
+        let dq = nq as i32 - q as i32;
+        let dr = nr as i32 - r as i32;
+
+        HexDirection::from((dq, dr))
+    }
+}
+
+impl From<(i32, i32)> for HexDirection {
+    fn from(value: (i32, i32)) -> Self {
+        match value {
+            (1, 0) => HexDirection::East,
+            (1, -1) => HexDirection::NorthEast,
+            (0, -1) => HexDirection::NorthWest,
+            (-1, 0) => HexDirection::West,
+            (-1, 1) => HexDirection::SouthWest,
+            (0, 1) => HexDirection::SouthEast,
+            _ => panic!("Invalid direction"),
+        }
+    }
+}
+
+impl From<usize> for HexDirection {
+    fn from(value: usize) -> Self {
+        HexDirection::all()
+            .get(value)
+            .copied()
+            .expect("Invalid direction")
+    }
+}
+
+impl From<HexDirection> for usize {
+    fn from(value: HexDirection) -> Self {
+        match value {
+            HexDirection::East => 0,
+            HexDirection::NorthEast => 1,
+            HexDirection::NorthWest => 2,
+            HexDirection::West => 3,
+            HexDirection::SouthWest => 4,
+            HexDirection::SouthEast => 5,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,4 +434,45 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn direction1() {
+        for (idx, d) in Direction1::all().into_iter().enumerate() {
+            assert_eq!(usize::from(d), idx);
+            assert_eq!(Direction1::from(idx), d);
+            assert_eq!(d.opposite().opposite(), d);
+            assert_eq!(d.opposite().offset(), -d.offset());
+        }
+    }
+
+    #[test]
+    fn direction3() {
+        for (idx, d) in Direction3::all().into_iter().enumerate() {
+            assert_eq!(usize::from(d), idx);
+            assert_eq!(Direction3::from(idx), d);
+            assert_eq!(d.opposite().opposite(), d);
+
+            let (x, y, z) = d.offset();
+            assert_eq!(d.opposite().offset(), (-x, -y, -z));
+        }
+    }
+
+    #[test]
+    fn hex_direction() {
+        for (idx, d) in HexDirection::all().into_iter().enumerate() {
+            assert_eq!(usize::from(d), idx);
+            assert_eq!(HexDirection::from(idx), d);
+            assert_eq!(HexDirection::from(d.offset()), d);
+            assert_eq!(d.opposite().add_pos(d.add_pos((3, 4))), (3, 4));
+        }
+
+        assert_eq!(
+            HexDirection::from_neighbors((1, 1), (2, 0)),
+            HexDirection::NorthEast
+        );
+        assert_eq!(
+            HexDirection::from_neighbors((1, 1), (0, 2)),
+            HexDirection::SouthWest
+        );
+    }
 }
@@ -0,0 +1,205 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// The ways in which generating a texture can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WfcError {
+    /// The pattern size is zero.
+    ZeroPatternSize,
+    /// The pattern doesn't fit inside the sample.
+    PatternTooLarge {
+        pattern_size: usize,
+        width: usize,
+        height: usize,
+    },
+    /// The symmetry isn't one of `1`, `2`, `4` or `8`.
+    InvalidSymmetry { symmetry: usize },
+    /// The output has no pixels.
+    ZeroOutputSize { width: usize, height: usize },
+    /// The output is too large to be stored as an image.
+    OutputTooLarge { width: usize, height: usize },
+    /// There are no patterns to generate the output from.
+    NoPatterns,
+    /// The temperature isn't a finite, positive number.
+    InvalidTemperature { temperature: f64 },
+    /// The solver ran into a contradiction that no amount of backtracking
+    /// can get it out of.
+    Contradiction {
+        /// The position of the slot left without possibilities.
+        cell: (usize, usize),
+        /// The amount of observations made before the contradiction.
+        observations: usize,
+    },
+    /// The solver ran into a contradiction after backtracking as many times
+    /// as it was allowed to.
+    BacktrackLimitReached {
+        /// The maximum amount of backtracks.
+        limit: usize,
+        /// The position of the slot left without possibilities.
+        cell: (usize, usize),
+        /// The amount of observations made before the contradiction.
+        observations: usize,
+    },
+    /// A file couldn't be read.
+    Io { path: PathBuf, message: String },
+    /// The description of a tileset is malformed.
+    InvalidTileset { message: String },
+    /// A neighbor rule refers to a tile that isn't in the tileset.
+    UnknownTile { name: String },
+    /// The symmetry class of a tile isn't one of `X`, `L`, `T`, `I`, `\`
+    /// or `F`.
+    InvalidSymmetryClass { tile: String, symmetry: String },
+    /// A grid of cell values is malformed.
+    InvalidGrid { message: String },
+    /// A MagicaVoxel file is malformed, or a model or palette can't be stored
+    /// in one.
+    InvalidVox { message: String },
+    /// A voxel pattern doesn't fit inside the sample.
+    VoxelPatternTooLarge {
+        pattern_size: usize,
+        size: (usize, usize, usize),
+    },
+    /// The voxel output has no voxels.
+    ZeroVoxelOutputSize { size: (usize, usize, usize) },
+    /// Like [`WfcError::Contradiction`], for a voxel output.
+    VoxelContradiction {
+        /// The position of the voxel left without possibilities.
+        voxel: (usize, usize, usize),
+        /// The amount of observations made before the contradiction.
+        observations: usize,
+    },
+    /// Like [`WfcError::BacktrackLimitReached`], for a voxel output.
+    VoxelBacktrackLimitReached {
+        /// The maximum amount of backtracks.
+        limit: usize,
+        /// The position of the voxel left without possibilities.
+        voxel: (usize, usize, usize),
+        /// The amount of observations made before the contradiction.
+        observations: usize,
+    },
+    /// The cells and edges of a topology don't fit together.
+    InvalidTopology { message: String },
+    /// Like [`WfcError::Contradiction`], for an output laid out on a
+    /// [`Topology`](crate::Topology).
+    NodeContradiction {
+        /// The index of the cell left without possibilities.
+        node: usize,
+        /// The amount of observations made before the contradiction.
+        observations: usize,
+    },
+    /// Like [`WfcError::BacktrackLimitReached`], for an output laid out on a
+    /// [`Topology`](crate::Topology).
+    NodeBacktrackLimitReached {
+        /// The maximum amount of backtracks.
+        limit: usize,
+        /// The index of the cell left without possibilities.
+        node: usize,
+        /// The amount of observations made before the contradiction.
+        observations: usize,
+    },
+    /// A legend mapping the characters of a text grid to colors is
+    /// malformed, or lacks a character.
+    InvalidLegend { message: String },
+    /// A pattern of a model generating a cube isn't part of the model
+    /// turned a quarter, which it needs to be put across turned edges.
+    MissingRotation { pattern: usize },
+}
+
+impl Display for WfcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WfcError::ZeroPatternSize => write!(f, "the pattern size must be at least 1"),
+            WfcError::PatternTooLarge {
+                pattern_size,
+                width,
+                height,
+            } => write!(
+                f,
+                "a pattern of size {pattern_size} doesn't fit in a {width}x{height} sample"
+            ),
+            WfcError::InvalidSymmetry { symmetry } => {
+                write!(f, "the symmetry must be 1, 2, 4 or 8, got {symmetry}")
+            }
+            WfcError::ZeroOutputSize { width, height } => {
+                write!(f, "can't generate a {width}x{height} output")
+            }
+            WfcError::OutputTooLarge { width, height } => {
+                write!(f, "a {width}x{height} output doesn't fit in an image")
+            }
+            WfcError::NoPatterns => write!(f, "there are no patterns to generate from"),
+            WfcError::InvalidTemperature { temperature } => {
+                write!(f, "the temperature must be positive, got {temperature}")
+            }
+            WfcError::Contradiction { cell, observations } => write!(
+                f,
+                "contradiction at {cell:?} after {observations} observations"
+            ),
+            WfcError::BacktrackLimitReached {
+                limit,
+                cell,
+                observations,
+            } => write!(
+                f,
+                "contradiction at {cell:?} after {observations} observations \
+                 and {limit} backtracks"
+            ),
+            WfcError::Io { path, message } => write!(f, "{}: {message}", path.display()),
+            WfcError::InvalidTileset { message } => write!(f, "invalid tileset: {message}"),
+            WfcError::UnknownTile { name } => write!(f, "there is no tile named {name:?}"),
+            WfcError::InvalidSymmetryClass { tile, symmetry } => write!(
+                f,
+                "the symmetry of tile {tile:?} must be X, L, T, I, \\ or F, got {symmetry:?}"
+            ),
+            WfcError::InvalidGrid { message } => write!(f, "invalid grid: {message}"),
+            WfcError::InvalidVox { message } => write!(f, "invalid .vox file: {message}"),
+            WfcError::VoxelPatternTooLarge {
+                pattern_size,
+                size: (x, y, z),
+            } => write!(
+                f,
+                "a pattern of size {pattern_size} doesn't fit in a {x}x{y}x{z} sample"
+            ),
+            WfcError::ZeroVoxelOutputSize { size: (x, y, z) } => {
+                write!(f, "can't generate a {x}x{y}x{z} output")
+            }
+            WfcError::VoxelContradiction {
+                voxel,
+                observations,
+            } => write!(
+                f,
+                "contradiction at {voxel:?} after {observations} observations"
+            ),
+            WfcError::VoxelBacktrackLimitReached {
+                limit,
+                voxel,
+                observations,
+            } => write!(
+                f,
+                "contradiction at {voxel:?} after {observations} observations \
+                 and {limit} backtracks"
+            ),
+            WfcError::InvalidTopology { message } => write!(f, "invalid topology: {message}"),
+            WfcError::NodeContradiction { node, observations } => write!(
+                f,
+                "contradiction at cell {node} after {observations} observations"
+            ),
+            WfcError::NodeBacktrackLimitReached {
+                limit,
+                node,
+                observations,
+            } => write!(
+                f,
+                "contradiction at cell {node} after {observations} observations \
+                 and {limit} backtracks"
+            ),
+            WfcError::InvalidLegend { message } => write!(f, "invalid legend: {message}"),
+            WfcError::MissingRotation { pattern } => write!(
+                f,
+                "pattern {pattern} isn't in the model turned a quarter, which a cube needs; \
+                 use a symmetry of 8"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WfcError {}
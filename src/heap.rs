@@ -0,0 +1,159 @@
+/// The position of an item that isn't in the heap.
+const ABSENT: usize = usize::MAX;
+
+/// The amount of children of each entry.
+///
+/// Priorities mostly go down, so items mostly move up the heap, which takes
+/// fewer steps in a wider heap.
+const ARITY: usize = 4;
+
+/// A min-heap over the items `0..len`, where the priority of each item can
+/// be changed in place.
+#[derive(Debug, Clone)]
+pub struct IndexedHeap {
+    /// The items and their priorities, ordered so that each one has a lower
+    /// priority than its children.
+    ///
+    /// Priorities are kept next to their items, so that sifting an item
+    /// doesn't have to look them up elsewhere.
+    heap: Vec<(f64, usize)>,
+    /// The position of each item in `heap`, or `ABSENT`.
+    positions: Vec<usize>,
+}
+
+impl IndexedHeap {
+    /// Creates an empty heap that can hold the items in `0..len`.
+    pub fn new(len: usize) -> Self {
+        IndexedHeap {
+            heap: Vec::with_capacity(len),
+            positions: vec![ABSENT; len],
+        }
+    }
+
+    /// The item with the lowest priority.
+    pub fn peek(&self) -> Option<usize> {
+        self.heap.first().map(|&(_, item)| item)
+    }
+
+    pub fn contains(&self, item: usize) -> bool {
+        self.positions[item] != ABSENT
+    }
+
+    /// Adds `item` to the heap with the given priority, or changes its
+    /// priority if it's already there.
+    pub fn push(&mut self, item: usize, priority: f64) {
+        if !self.contains(item) {
+            self.heap.push((priority, item));
+            self.sift_up(self.heap.len() - 1);
+            return;
+        }
+
+        let position = self.positions[item];
+        let old = std::mem::replace(&mut self.heap[position].0, priority);
+        if priority < old {
+            self.sift_up(position);
+        } else {
+            self.sift_down(position);
+        }
+    }
+
+    /// Removes `item` from the heap, returning whether it was there.
+    pub fn remove(&mut self, item: usize) -> bool {
+        if !self.contains(item) {
+            return false;
+        }
+
+        let position = self.positions[item];
+        self.positions[item] = ABSENT;
+        let last = self.heap.pop().unwrap();
+        if position < self.heap.len() {
+            self.heap[position] = last;
+            self.sift_down(position);
+            self.sift_up(self.positions[last.1]);
+        }
+
+        true
+    }
+
+    /// Moves the entry at `position` up until its parent has a lower
+    /// priority.
+    fn sift_up(&mut self, mut position: usize) {
+        let entry = self.heap[position];
+        while position > 0 {
+            let parent = (position - 1) / ARITY;
+            if entry.0 >= self.heap[parent].0 {
+                break;
+            }
+
+            self.heap[position] = self.heap[parent];
+            self.positions[self.heap[position].1] = position;
+            position = parent;
+        }
+
+        self.heap[position] = entry;
+        self.positions[entry.1] = position;
+    }
+
+    /// Moves the entry at `position` down until its children have higher
+    /// priorities.
+    fn sift_down(&mut self, mut position: usize) {
+        let entry = self.heap[position];
+        loop {
+            let first = ARITY * position + 1;
+            let last = (first + ARITY).min(self.heap.len());
+            if first >= last {
+                break;
+            }
+            let mut child = first;
+            for other in first + 1..last {
+                if self.heap[other].0 < self.heap[child].0 {
+                    child = other;
+                }
+            }
+            if self.heap[child].0 >= entry.0 {
+                break;
+            }
+
+            self.heap[position] = self.heap[child];
+            self.positions[self.heap[position].1] = position;
+            position = child;
+        }
+
+        self.heap[position] = entry;
+        self.positions[entry.1] = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexedHeap;
+
+    #[test]
+    fn push_and_remove() {
+        let mut heap = IndexedHeap::new(5);
+        assert_eq!(heap.peek(), None);
+
+        for (item, priority) in [(0, 3.0), (1, 1.0), (2, 4.0), (3, 2.0)] {
+            heap.push(item, priority);
+        }
+        assert_eq!(heap.peek(), Some(1));
+        assert!(!heap.contains(4));
+
+        // Raising and lowering priorities reorders the heap.
+        heap.push(1, 5.0);
+        assert_eq!(heap.peek(), Some(3));
+        heap.push(2, 0.5);
+        assert_eq!(heap.peek(), Some(2));
+
+        assert!(heap.remove(2));
+        assert!(!heap.remove(2));
+        assert_eq!(heap.peek(), Some(3));
+
+        let mut order = vec![];
+        while let Some(item) = heap.peek() {
+            order.push(item);
+            heap.remove(item);
+        }
+        assert_eq!(order, vec![3, 0, 1]);
+    }
+}
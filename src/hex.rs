@@ -0,0 +1,568 @@
+use std::ops::{Index, IndexMut};
+
+use itertools::iproduct;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::compatibility::Compatibility;
+use crate::direction::HexDirection;
+use crate::error::WfcError;
+use crate::pattern;
+use crate::table::{wrap_coordinate, Wrap};
+use crate::topology::Topology;
+use crate::wfc::{solve_graph, Graph, DEFAULT_BACKTRACK_LIMIT};
+
+/// A grid of hexagonal cells, in axial coordinates `(q, r)`.
+///
+/// The cells are stored row by row, where each row has the same `r`. Since
+/// each row is shifted half a cell to the right of the previous one, the
+/// grid is shaped like a parallelogram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexTable<T> {
+    collection: Vec<T>,
+    width: usize,
+    wrap: Wrap,
+}
+
+impl<T> HexTable<T> {
+    pub fn new(collection: Vec<T>, width: usize) -> Self {
+        HexTable {
+            collection,
+            width,
+            wrap: Wrap::None,
+        }
+    }
+
+    /// Makes the table wrap around along the given axes, so that cells on
+    /// opposite borders are neighbors. `Horizontal` wraps the `q` axis and
+    /// `Vertical` the `r` axis.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// The amount of cells in each row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The amount of rows.
+    pub fn height(&self) -> usize {
+        self.collection.len().checked_div(self.width).unwrap_or(0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.collection.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.collection.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_empty()
+    }
+
+    pub fn idx_to_pos(&self, idx: usize) -> (usize, usize) {
+        (idx % self.width, idx / self.width)
+    }
+
+    pub fn pos_to_idx(&self, (q, r): (usize, usize)) -> usize {
+        r * self.width + q
+    }
+
+    /// Returns the neighbors of the cell at the given position, along with
+    /// the direction in which each of them lies.
+    ///
+    /// Cells on the borders have fewer neighbors, unless the table wraps
+    /// around that border.
+    pub fn get_neighbors(&self, (q, r): (usize, usize)) -> Vec<(HexDirection, (usize, usize))> {
+        let mut neighbors = Vec::with_capacity(6);
+
+        for d in HexDirection::all() {
+            let (nq, nr) = d.add_pos((q as i32, r as i32));
+            let Some(nq) = wrap_coordinate(nq, self.width(), self.wrap.wraps_x()) else {
+                continue;
+            };
+            let Some(nr) = wrap_coordinate(nr, self.height(), self.wrap.wraps_y()) else {
+                continue;
+            };
+
+            neighbors.push((d, (nq, nr)));
+        }
+
+        neighbors
+    }
+
+    /// The cell at the given position, wrapping around every border.
+    fn get_wrapped(&self, (q, r): (i32, i32)) -> &T {
+        let q = q.rem_euclid(self.width() as i32) as usize;
+        let r = r.rem_euclid(self.height() as i32) as usize;
+        &self[(q, r)]
+    }
+}
+
+impl<T> Topology for HexTable<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn directions(&self) -> usize {
+        6
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        self.get_neighbors(self.idx_to_pos(idx))
+            .into_iter()
+            .map(|(d, pos)| (usize::from(d), self.pos_to_idx(pos)))
+            .collect()
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        usize::from(HexDirection::from(direction).opposite())
+    }
+}
+
+impl HexTable<String> {
+    /// Parses a grid with a row per line, and cells separated by
+    /// whitespace.
+    ///
+    /// ```text
+    /// grass grass water
+    ///  grass water water
+    ///   sand water water
+    /// ```
+    pub fn from_text(text: &str) -> Result<Self, WfcError> {
+        let rows = text
+            .lines()
+            .map(|line| line.split_whitespace().map(String::from).collect())
+            .filter(|row: &Vec<String>| !row.is_empty())
+            .collect();
+
+        HexTable::from_rows(rows)
+    }
+
+    /// Parses a grid given as a JSON array of rows, where each cell is a
+    /// string or any other JSON value, which is then turned into a string.
+    ///
+    /// ```json
+    /// [["grass", "grass", "water"], ["grass", "water", "water"]]
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self, WfcError> {
+        let rows: Vec<Vec<serde_json::Value>> =
+            serde_json::from_str(json).map_err(|err| WfcError::InvalidGrid {
+                message: err.to_string(),
+            })?;
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|value| match value {
+                        serde_json::Value::String(value) => value,
+                        value => value.to_string(),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        HexTable::from_rows(rows)
+    }
+
+    /// Writes the grid with a row per line, and cells separated by spaces,
+    /// in the format read by [`HexTable::from_text`].
+    pub fn to_text(&self) -> String {
+        self.collection
+            .chunks(self.width.max(1))
+            .map(|row| row.join(" ") + "\n")
+            .collect()
+    }
+
+    fn from_rows(rows: Vec<Vec<String>>) -> Result<Self, WfcError> {
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(r) = rows.iter().position(|row| row.len() != width) {
+            return Err(WfcError::InvalidGrid {
+                message: format!(
+                    "row {r} has {} cells, but the first one has {width}",
+                    rows[r].len()
+                ),
+            });
+        }
+
+        Ok(HexTable::new(rows.concat(), width))
+    }
+}
+
+impl<T> Index<usize> for HexTable<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.collection.index(index)
+    }
+}
+
+impl<T> IndexMut<usize> for HexTable<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.collection.index_mut(index)
+    }
+}
+
+impl<T> Index<(usize, usize)> for HexTable<T> {
+    type Output = T;
+
+    fn index(&self, pos: (usize, usize)) -> &Self::Output {
+        self.collection.index(self.pos_to_idx(pos))
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for HexTable<T> {
+    fn index_mut(&mut self, pos: (usize, usize)) -> &mut Self::Output {
+        let idx = self.pos_to_idx(pos);
+        self.collection.index_mut(idx)
+    }
+}
+
+/// The overlapping model on a hexagonal grid.
+///
+/// Patterns are the hexagons of a given radius around each cell of a sample
+/// map, and two patterns can be put next to each other if they agree on
+/// the cells they share.
+pub struct HexModel {
+    /// The distinct cell values of the sample.
+    values: Vec<String>,
+    /// The offsets from the center of a pattern to each of its cells.
+    offsets: Vec<(i32, i32)>,
+    /// The value of each cell of each pattern, as an index into `values`,
+    /// in the order of `offsets`.
+    patterns: Vec<Vec<usize>>,
+    /// The amount of times each pattern appears in the sample.
+    frequencies: Vec<usize>,
+    /// The patterns that overlap each pattern in each direction.
+    compatibility: Compatibility,
+    /// The maximum amount of times the solver may backtrack in a single
+    /// generation.
+    backtrack_limit: usize,
+    /// The axes along which the output wraps around.
+    wrap: Wrap,
+}
+
+impl HexModel {
+    /// Extracts the hexagons of the given radius around the cells of the
+    /// sample, which wraps around its borders if `periodic_input` is set.
+    ///
+    /// A radius of `0` only looks at single cells, and `1` at a cell and
+    /// its six neighbors.
+    pub fn new(
+        sample: &HexTable<String>,
+        radius: usize,
+        periodic_input: bool,
+    ) -> Result<Self, WfcError> {
+        let pattern_size = 2 * radius + 1;
+        let (width, height) = (sample.width(), sample.height());
+        if !periodic_input && (pattern_size > width || pattern_size > height) {
+            return Err(WfcError::PatternTooLarge {
+                pattern_size,
+                width,
+                height,
+            });
+        }
+
+        let (values, cells) = pattern::intern(sample.iter().cloned());
+        let cells = HexTable::new(cells, width);
+
+        let offsets = hexagon(radius as i32);
+        let centers = |len: usize| {
+            if periodic_input {
+                0..len
+            } else {
+                radius..len - radius
+            }
+        };
+
+        let windows = iproduct!(centers(height), centers(width)).map(|(r, q)| {
+            offsets
+                .iter()
+                .map(|&(dq, dr)| *cells.get_wrapped((q as i32 + dq, r as i32 + dr)))
+                .collect::<Vec<_>>()
+        });
+        let (patterns, frequencies) = pattern::count(windows);
+
+        let compatibility = build_constraints(&patterns, &offsets);
+
+        Ok(HexModel {
+            values,
+            offsets,
+            patterns,
+            frequencies,
+            compatibility,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            wrap: Wrap::None,
+        })
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Sets the axes along which the output wraps around. See
+    /// [`HexTable::with_wrap`].
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Generates a map with `height` rows of `width` cells.
+    ///
+    /// The same seed always produces the same output for the same sample.
+    pub fn generate(
+        &self,
+        width: usize,
+        height: usize,
+        seed: u64,
+    ) -> Result<HexTable<String>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(width, height, &mut rng)
+    }
+
+    /// Like [`HexModel::generate`], but draws every random choice from
+    /// `rng`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> Result<HexTable<String>, WfcError> {
+        if width == 0 || height == 0 {
+            return Err(WfcError::ZeroOutputSize { width, height });
+        }
+
+        let cells = HexTable::new(vec![(); width * height], width).with_wrap(self.wrap);
+        let graph = Graph::new(&cells);
+
+        let weights = self.frequencies.iter().map(|&f| f as f64).collect();
+        let ids = solve_graph(
+            &self.compatibility,
+            weights,
+            &graph,
+            self.backtrack_limit,
+            rng,
+        )
+        .map_err(|failure| failure.into_error(|slot| cells.idx_to_pos(slot)))?;
+
+        let center = self.offsets.iter().position(|&o| o == (0, 0)).unwrap();
+        let output = ids
+            .into_iter()
+            .map(|id| self.values[self.patterns[id][center]].clone())
+            .collect();
+
+        Ok(HexTable::new(output, width).with_wrap(self.wrap))
+    }
+}
+
+/// The offsets of the cells at most `radius` steps away from a cell, in
+/// increasing order.
+fn hexagon(radius: i32) -> Vec<(i32, i32)> {
+    let mut offsets = Vec::new();
+    for dq in -radius..=radius {
+        for dr in -radius..=radius {
+            if (dq + dr).abs() <= radius {
+                offsets.push((dq, dr));
+            }
+        }
+    }
+
+    offsets
+}
+
+/// Builds the constraints table, where a pattern is compatible with another
+/// in a direction if they agree on the cells they share when the second one
+/// is a step away from the first in that direction.
+fn build_constraints(patterns: &[Vec<usize>], offsets: &[(i32, i32)]) -> Compatibility {
+    // The cells of a pattern facing each direction: the pattern's cell at
+    // `o + d`, for every offset `o` such that `o + d` is in the pattern.
+    let index: HashMap<_, _> = offsets.iter().enumerate().map(|(i, &o)| (o, i)).collect();
+    let sides = HexDirection::all().map(|d| {
+        offsets
+            .iter()
+            .filter_map(|&o| index.get(&d.add_pos(o)).copied())
+            .collect::<Vec<_>>()
+    });
+    let side = |pattern: &[usize], d: HexDirection| {
+        sides[usize::from(d)]
+            .iter()
+            .map(|&i| pattern[i])
+            .collect::<Vec<_>>()
+    };
+
+    Compatibility::overlapping(
+        patterns.iter().enumerate(),
+        &HexDirection::all(),
+        |d| d.opposite(),
+        |pattern, d| side(pattern, d),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{HexModel, HexTable};
+    use crate::direction::HexDirection;
+    use crate::error::WfcError;
+    use crate::table::Wrap;
+    use crate::test_utils::assert_deterministic;
+
+    /// A map whose cells cycle through `a`, `b` and `c` towards the east,
+    /// and are the same towards the south east.
+    fn stripes(width: usize, height: usize) -> HexTable<String> {
+        let cells = (0..width * height)
+            .map(|idx| ["a", "b", "c"][idx % width % 3].to_string())
+            .collect();
+        HexTable::new(cells, width)
+    }
+
+    /// Checks that every cell is followed by the next value of the stripes
+    /// towards the east, and by the same value towards the south east.
+    fn assert_striped(map: &HexTable<String>) {
+        let next = |value: &str| match value {
+            "a" => "b",
+            "b" => "c",
+            _ => "a",
+        };
+
+        for idx in 0..map.len() {
+            let pos = map.idx_to_pos(idx);
+            for (d, neighbor) in map.get_neighbors(pos) {
+                match d {
+                    HexDirection::East => assert_eq!(map[neighbor], next(&map[pos])),
+                    HexDirection::SouthEast => assert_eq!(map[neighbor], map[pos]),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn get_neighbors() {
+        // [0, 1, 2]
+        //  [3, 4, 5]
+        //   [6, 7, 8]
+        let table = HexTable::new((0..9).collect_vec(), 3);
+        assert_eq!(table.height(), 3);
+        assert_eq!(table.idx_to_pos(5), (2, 1));
+        assert_eq!(table[(2, 1)], 5);
+
+        let neighbors = table.get_neighbors((1, 1));
+        assert_eq!(neighbors.len(), 6);
+        for (d, neighbor) in neighbors {
+            assert_eq!(HexDirection::from_neighbors((1, 1), neighbor), d);
+        }
+
+        let neighbors = table.get_neighbors((0, 0));
+        assert_eq!(
+            neighbors,
+            vec![
+                (HexDirection::East, (1, 0)),
+                (HexDirection::SouthEast, (0, 1)),
+            ]
+        );
+
+        let neighbors = table.get_neighbors((2, 0));
+        assert_eq!(
+            neighbors,
+            vec![
+                (HexDirection::West, (1, 0)),
+                (HexDirection::SouthWest, (1, 1)),
+                (HexDirection::SouthEast, (2, 1)),
+            ]
+        );
+
+        let table = table.with_wrap(Wrap::Both);
+        assert_eq!(table.get_neighbors((0, 0)).len(), 6);
+        assert!(table
+            .get_neighbors((0, 0))
+            .contains(&(HexDirection::NorthEast, (1, 2))));
+    }
+
+    #[test]
+    fn parse() {
+        let text = "a b c\n b c a\n\n";
+        let table = HexTable::from_text(text).unwrap();
+        assert_eq!(table.width(), 3);
+        assert_eq!(table.height(), 2);
+        assert_eq!(table[(0, 1)], "b");
+        assert_eq!(table.to_text(), "a b c\nb c a\n");
+
+        let json = r#"[["a", "b", "c"], ["b", "c", "a"]]"#;
+        assert_eq!(HexTable::from_json(json), Ok(table));
+
+        let json = "[[1, 2], [2, 1]]";
+        assert_eq!(HexTable::from_json(json).unwrap()[(1, 0)], "2");
+
+        assert!(matches!(
+            HexTable::from_text("a b\nc"),
+            Err(WfcError::InvalidGrid { .. })
+        ));
+        assert!(matches!(
+            HexTable::from_json("{}"),
+            Err(WfcError::InvalidGrid { .. })
+        ));
+    }
+
+    #[test]
+    fn generate() {
+        let model = HexModel::new(&stripes(6, 3), 1, true).unwrap();
+        assert_eq!(model.patterns.len(), 3);
+        assert_eq!(model.offsets.len(), 7);
+
+        for output in assert_deterministic(|seed| model.generate(7, 5, seed)) {
+            assert_eq!((output.width(), output.height()), (7, 5));
+            assert_striped(&output);
+        }
+
+        // The stripes can only wrap around if the width is a multiple of 3.
+        let model = model.with_wrap(Wrap::Both);
+        assert_striped(&model.generate(6, 4, 0).unwrap());
+        assert!(model.generate(4, 4, 0).is_err());
+
+        // Single cells can be put anywhere.
+        let model = HexModel::new(&stripes(3, 1), 0, false).unwrap();
+        let output = model.generate(4, 4, 0).unwrap();
+        assert!(output
+            .iter()
+            .all(|value| ["a", "b", "c"].contains(&&**value)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            HexModel::new(&stripes(6, 2), 1, false).err(),
+            Some(WfcError::PatternTooLarge {
+                pattern_size: 3,
+                width: 6,
+                height: 2
+            })
+        );
+
+        let model = HexModel::new(&stripes(6, 3), 1, true).unwrap();
+        assert_eq!(
+            model.generate(0, 2, 0),
+            Err(WfcError::ZeroOutputSize {
+                width: 0,
+                height: 2
+            })
+        );
+
+        let model = model.with_wrap(Wrap::Horizontal).with_backtrack_limit(0);
+        assert!(matches!(
+            model.generate(4, 1, 0),
+            Err(WfcError::Contradiction { .. } | WfcError::BacktrackLimitReached { .. })
+        ));
+
+        let empty = HexModel::new(&HexTable::new(vec![], 0), 0, true).unwrap();
+        assert_eq!(empty.generate(1, 1, 0), Err(WfcError::NoPatterns));
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use image::Rgb;
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::error::WfcError;
+use crate::texture::Texture;
+use crate::Image;
+
+/// The color of each character of a text grid, used to draw it as an image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Legend {
+    colors: HashMap<char, Rgb<u8>>,
+}
+
+impl Legend {
+    pub fn new() -> Self {
+        Legend::default()
+    }
+
+    /// Sets the color of a character.
+    pub fn with_color(mut self, c: char, color: Rgb<u8>) -> Self {
+        self.colors.insert(c, color);
+        self
+    }
+
+    /// Parses a legend with a character per line, followed by its color,
+    /// either as a hex code or as its red, green and blue channels.
+    ///
+    /// ```text
+    /// # #404040
+    /// . 200 180 150
+    /// ~ #1e3cc8
+    /// ```
+    ///
+    /// Empty lines are skipped.
+    pub fn from_text(text: &str) -> Result<Self, WfcError> {
+        let mut legend = Legend::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut chars = line.chars();
+            let c = chars.next().unwrap();
+            let color = chars.as_str();
+            if color.trim().is_empty() {
+                return Err(invalid(format!("line {} has no color", i + 1)));
+            }
+            if !color.starts_with(char::is_whitespace) {
+                return Err(invalid(format!(
+                    "line {} doesn't start with a single character",
+                    i + 1
+                )));
+            }
+
+            let color = parse_color(color.trim())
+                .ok_or_else(|| invalid(format!("line {} has an invalid color", i + 1)))?;
+            if legend.colors.insert(c, color).is_some() {
+                return Err(invalid(format!("{c:?} is given more than one color")));
+            }
+        }
+
+        Ok(legend)
+    }
+
+    /// Reads a legend from a file. See [`Legend::from_text`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+        Legend::from_text(&text)
+    }
+
+    pub fn get(&self, c: char) -> Option<Rgb<u8>> {
+        self.colors.get(&c).copied()
+    }
+
+    /// Draws a text grid as an image, a pixel per character.
+    ///
+    /// Fails if a character of the grid has no color.
+    pub fn render(&self, texture: &Texture<char>) -> Result<Image, WfcError> {
+        if let Some(&c) = texture.iter().find(|&&c| self.get(c).is_none()) {
+            return Err(invalid(format!("{c:?} has no color")));
+        }
+
+        Ok(texture.map(|&c| self.colors[&c]).to_image())
+    }
+}
+
+/// Parses `#rrggbb`, or three decimal channels separated by whitespace.
+fn parse_color(color: &str) -> Option<Rgb<u8>> {
+    if let Some(hex) = color.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok();
+        return Some(Rgb([channel(0)?, channel(1)?, channel(2)?]));
+    }
+
+    let channels = color
+        .split_whitespace()
+        .map(|channel| channel.parse().ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(Rgb(channels.try_into().ok()?))
+}
+
+fn invalid(message: impl Into<String>) -> WfcError {
+    WfcError::InvalidLegend {
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgb;
+
+    use super::Legend;
+    use crate::error::WfcError;
+    use crate::texture::Texture;
+
+    #[test]
+    fn from_text() {
+        let legend = Legend::from_text("# #404040\n\n. 200 180 150\n  0 0 0\n").unwrap();
+        assert_eq!(
+            legend,
+            Legend::new()
+                .with_color('#', Rgb([64, 64, 64]))
+                .with_color('.', Rgb([200, 180, 150]))
+                .with_color(' ', Rgb([0, 0, 0]))
+        );
+
+        for text in [
+            "#\n",
+            "## #000000\n",
+            "# #00000\n",
+            "# 0 0\n",
+            "# 0 0 256\n",
+        ] {
+            assert!(
+                matches!(Legend::from_text(text), Err(WfcError::InvalidLegend { .. })),
+                "{text:?}"
+            );
+        }
+        assert_eq!(
+            Legend::from_text("# 0 0 0\n# 1 1 1\n"),
+            Err(WfcError::InvalidLegend {
+                message: "'#' is given more than one color".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn render() {
+        let legend = Legend::new()
+            .with_color('#', Rgb([0, 0, 0]))
+            .with_color('.', Rgb([255, 255, 255]));
+        let texture = Texture::from_text("#.\n..\n").unwrap();
+        let image = legend.render(&texture).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(image[(0, 0)], Rgb([0, 0, 0]));
+        assert_eq!(image[(1, 1)], Rgb([255, 255, 255]));
+
+        assert_eq!(
+            legend.render(&Texture::from_text("#~\n").unwrap()),
+            Err(WfcError::InvalidLegend {
+                message: "'~' has no color".to_string()
+            })
+        );
+    }
+}
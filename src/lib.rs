@@ -1,23 +1,164 @@
+mod bitset;
+mod compatibility;
+mod cube;
 mod direction;
+mod error;
+mod heap;
+mod hex;
+mod legend;
 mod pattern;
+mod sequence;
+mod sockets;
 mod table;
+#[cfg(test)]
 mod test_utils;
+mod texture;
+mod tiled;
+mod tiles;
+mod topology;
+mod vox;
+mod voxel;
+mod wang;
 mod wfc;
 
-pub use self::wfc::Wfc;
-pub use pattern::get_patterns;
+pub use self::wfc::{Solver, Wfc, DEFAULT_BACKTRACK_LIMIT};
+pub use compatibility::Compatibility;
+pub use cube::{CubeMap, CubeSphere, Face};
+pub use direction::{Direction, Direction1, Direction3, HexDirection};
+pub use error::WfcError;
+pub use hex::{HexModel, HexTable};
+pub use legend::Legend;
+#[cfg(feature = "parallel")]
+pub use pattern::par_get_patterns;
+pub use pattern::{augment, get_patterns};
+pub use sequence::SequenceModel;
+pub use sockets::{SocketModel, SocketRules, SocketTile, Sockets};
+pub use table::Wrap;
+pub use texture::{CellValue, Texture};
+pub use tiled::{SymmetryClass, TiledModel};
+pub use topology::{GraphTopology, Grid, Masked, Topology};
+pub use voxel::{VoxelGrid, VoxelModel};
+pub use wang::{Corners, WangModel, WangTile};
 
-type Image = image::ImageBuffer<image::Rgb<u8>, Vec<u8>>;
+use image::Rgb;
+
+type Image = image::ImageBuffer<Rgb<u8>, Vec<u8>>;
 
 pub struct Config {
     pub pattern_size: usize,
+    /// The amount of dihedral variants of each pattern to use, either `1`,
+    /// `2`, `4` or `8`. See [`augment`].
+    pub symmetry: usize,
+    /// Whether the input wraps around its borders when extracting patterns.
+    pub periodic_input: bool,
     pub width: usize,
     pub height: usize,
+    /// The axes along which the output wraps around.
+    pub wrap: Wrap,
+    /// The maximum amount of times the solver may backtrack after running
+    /// into a contradiction.
+    pub backtrack_limit: usize,
+    /// The seed for the random choices made by the solver.
+    ///
+    /// A random seed is used if none is given.
+    pub seed: Option<u64>,
+    /// How much the frequency of a pattern affects how likely it is to be
+    /// chosen. See [`Wfc::with_temperature`].
+    pub temperature: Option<f64>,
+}
+
+pub fn generate(image: Image, cfg: Config) -> Result<Image, WfcError> {
+    let sample = Texture::from_image(&image);
+    let patterns = patterns(&sample, &cfg, image_patterns)?;
+    let seed = cfg.seed.unwrap_or_else(rand::random);
+    let output = configure(image_solver(patterns.iter().collect()), &cfg)
+        .generate(cfg.width, cfg.height, seed)?;
+    Ok(output.to_image())
+}
+
+/// Like [`generate`], but takes the patterns from a texture of any kind of
+/// values, such as tile ids or characters, and generates another one.
+pub fn generate_texture<T: CellValue>(
+    sample: &Texture<T>,
+    cfg: Config,
+) -> Result<Texture<T>, WfcError> {
+    let patterns = patterns(sample, &cfg, pattern::get_patterns)?;
+    let seed = cfg.seed.unwrap_or_else(rand::random);
+    configure(Wfc::new(patterns.iter().collect()), &cfg).generate(cfg.width, cfg.height, seed)
+}
+
+/// Like [`generate`], but generates the six faces of a cube, `cfg.width`
+/// pixels wide. See [`Wfc::generate_cube`].
+pub fn generate_cube(image: Image, cfg: Config) -> Result<CubeMap<Rgb<u8>>, WfcError> {
+    let sample = Texture::from_image(&image);
+    let patterns = patterns(&sample, &cfg, image_patterns)?;
+    let seed = cfg.seed.unwrap_or_else(rand::random);
+    configure(image_solver(patterns.iter().collect()), &cfg).generate_cube(cfg.width, seed)
+}
+
+/// Extracts the patterns of the sample with `extract`, and their dihedral
+/// variants.
+fn patterns<T: CellValue>(
+    sample: &Texture<T>,
+    cfg: &Config,
+    extract: impl FnOnce(&Texture<T>, usize, bool) -> Vec<pattern::Pattern<T>>,
+) -> Result<Vec<pattern::Pattern<T>>, WfcError> {
+    if cfg.pattern_size == 0 {
+        return Err(WfcError::ZeroPatternSize);
+    }
+
+    let (sample_width, sample_height) = sample.dimensions();
+    if cfg.pattern_size > sample_width || cfg.pattern_size > sample_height {
+        return Err(WfcError::PatternTooLarge {
+            pattern_size: cfg.pattern_size,
+            width: sample_width,
+            height: sample_height,
+        });
+    }
+
+    let patterns = extract(sample, cfg.pattern_size, cfg.periodic_input);
+    pattern::augment(patterns, cfg.symmetry)
+}
+
+/// Extracts the patterns of an image, in parallel with the `parallel`
+/// feature.
+#[cfg(feature = "parallel")]
+fn image_patterns(
+    sample: &Texture<Rgb<u8>>,
+    size: usize,
+    periodic: bool,
+) -> Vec<pattern::Pattern<Rgb<u8>>> {
+    pattern::par_get_patterns(sample, size, periodic)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn image_patterns(
+    sample: &Texture<Rgb<u8>>,
+    size: usize,
+    periodic: bool,
+) -> Vec<pattern::Pattern<Rgb<u8>>> {
+    pattern::get_patterns(sample, size, periodic)
+}
+
+/// Creates the solver of the patterns of an image, building its
+/// constraints in parallel with the `parallel` feature.
+#[cfg(feature = "parallel")]
+fn image_solver(patterns: Vec<&pattern::Pattern<Rgb<u8>>>) -> Wfc<'_, Rgb<u8>> {
+    Wfc::par_new(patterns)
+}
+
+#[cfg(not(feature = "parallel"))]
+fn image_solver(patterns: Vec<&pattern::Pattern<Rgb<u8>>>) -> Wfc<'_, Rgb<u8>> {
+    Wfc::new(patterns)
 }
 
-pub fn generate(image: Image, cfg: Config) -> Image {
-    let patterns = pattern::get_patterns(&image, cfg.pattern_size);
-    let patterns = patterns.iter().collect();
-    let solver = wfc::Wfc::new(patterns);
-    solver.generate(cfg.width as u32, cfg.height as u32)
+/// Applies the settings of `cfg` to the solver.
+fn configure<'p, T: CellValue>(solver: Wfc<'p, T>, cfg: &Config) -> Wfc<'p, T> {
+    let mut solver = solver
+        .with_backtrack_limit(cfg.backtrack_limit)
+        .with_wrap(cfg.wrap);
+    if let Some(temperature) = cfg.temperature {
+        solver = solver.with_temperature(temperature);
+    }
+    solver
 }
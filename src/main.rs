@@ -1,45 +1,296 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use clap::Parser;
-use image::ImageResult;
+use clap::{ArgAction, Parser};
 
-use wfc::{generate, Config};
+use wfc::{
+    generate, generate_cube, generate_texture, Config, Legend, Texture, TiledModel, VoxelGrid,
+    VoxelModel, WfcError, Wrap, DEFAULT_BACKTRACK_LIMIT,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)] // Read from `Cargo.toml`
 struct Cli {
-    /// Path to the texture to process.
+    /// Path to the texture to process, to the XML description of a tileset, to
+    /// a directory of tiles, to a MagicaVoxel `.vox` sample, or to a `.txt`
+    /// map with a character per cell.
     input_texture: PathBuf,
     /// Path to the output texture.
+    ///
+    /// Maps generated from a `.txt` sample are written as text to `.txt`
+    /// paths, drawn with `--legend` to any other path, and printed if there's
+    /// no path. Models generated from a `.vox` sample need a path.
     #[arg(short = 'o', long = "output")]
     output_texture: Option<PathBuf>,
     /// The pattern (kernel) size.
     #[arg(short = 's', long = "size", default_value = "2")]
     size: usize,
-    /// The width of the output image.
+    /// How many rotations and reflections of each pattern to use: 1, 2, 4 or 8.
+    #[arg(long = "symmetry", default_value = "1")]
+    symmetry: usize,
+    /// Whether the input texture wraps around its borders.
+    #[arg(long = "periodic-input", default_value_t = true, action = ArgAction::Set)]
+    periodic_input: bool,
+    /// The width of the output image, in tiles when using a tileset.
     #[arg(long = "width", default_value = "10")]
     width: usize,
-    /// The height of the output image.
+    /// The height of the output image, in tiles when using a tileset.
     #[arg(long = "height", default_value = "10")]
     height: usize,
+    /// The depth of the output, along the `y` axis, when using a `.vox` sample.
+    #[arg(long = "depth", default_value = "10")]
+    depth: usize,
+    /// Which borders of the output wrap around: none, horizontal, vertical or both.
+    #[arg(long = "wrap", default_value = "none")]
+    wrap: Wrap,
+    /// The maximum amount of times the solver may backtrack.
+    #[arg(long = "backtracks", default_value_t = DEFAULT_BACKTRACK_LIMIT)]
+    backtrack_limit: usize,
+    /// The seed for the random number generator.
+    ///
+    /// Running twice with the same seed produces the same output.
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+    /// Flattens (above 1) or sharpens (below 1) the pattern frequencies.
+    #[arg(long = "temperature")]
+    temperature: Option<f64>,
+    /// How much the color channels of touching tile edges may differ, when
+    /// using a directory of tiles.
+    #[arg(long = "tolerance", default_value = "0")]
+    tolerance: u8,
+    /// Splits the input texture into tiles of this size, and only puts tiles
+    /// next to each other the way they are in the input.
+    #[arg(long = "tile-size", conflicts_with = "cube")]
+    tile_size: Option<u32>,
+    /// Generates the six faces of a cube, `--width` pixels wide, laid out
+    /// as a cross. Needs `--symmetry 8`, unless every pattern of the sample
+    /// also shows up in it turned a quarter.
+    #[arg(long = "cube")]
+    cube: bool,
+    /// Path to the colors of the characters of a `.txt` sample, a character
+    /// and a color per line, such as `# #404040` or `. 200 180 150`.
+    #[arg(long = "legend")]
+    legend: Option<PathBuf>,
 }
 
-fn main() -> ImageResult<()> {
+/// The exit code used when the input or output can't be read or written.
+const EXIT_IO: u8 = 1;
+/// The exit code used when the arguments can't be used to generate a texture.
+const EXIT_INVALID_ARGS: u8 = 2;
+/// The exit code used when the solver fails to generate a texture.
+const EXIT_UNSOLVABLE: u8 = 3;
+
+fn exit_code(err: &WfcError) -> u8 {
+    match err {
+        WfcError::ZeroPatternSize
+        | WfcError::PatternTooLarge { .. }
+        | WfcError::InvalidSymmetry { .. }
+        | WfcError::ZeroOutputSize { .. }
+        | WfcError::OutputTooLarge { .. }
+        | WfcError::NoPatterns
+        | WfcError::InvalidTemperature { .. }
+        | WfcError::InvalidTileset { .. }
+        | WfcError::UnknownTile { .. }
+        | WfcError::InvalidSymmetryClass { .. }
+        | WfcError::InvalidGrid { .. }
+        | WfcError::InvalidVox { .. }
+        | WfcError::VoxelPatternTooLarge { .. }
+        | WfcError::ZeroVoxelOutputSize { .. }
+        | WfcError::InvalidTopology { .. }
+        | WfcError::InvalidLegend { .. }
+        | WfcError::MissingRotation { .. } => EXIT_INVALID_ARGS,
+        WfcError::Contradiction { .. }
+        | WfcError::BacktrackLimitReached { .. }
+        | WfcError::VoxelContradiction { .. }
+        | WfcError::VoxelBacktrackLimitReached { .. }
+        | WfcError::NodeContradiction { .. }
+        | WfcError::NodeBacktrackLimitReached { .. } => EXIT_UNSOLVABLE,
+        WfcError::Io { .. } => EXIT_IO,
+    }
+}
+
+/// The settings of the overlapping model, taken from the arguments.
+fn config(args: &Cli) -> Config {
+    Config {
+        pattern_size: args.size,
+        symmetry: args.symmetry,
+        periodic_input: args.periodic_input,
+        width: args.width,
+        height: args.height,
+        wrap: args.wrap,
+        backtrack_limit: args.backtrack_limit,
+        seed: args.seed,
+        temperature: args.temperature,
+    }
+}
+
+/// Whether `path` has the extension `ext`.
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension().is_some_and(|e| e == ext)
+}
+
+/// Finds flags that don't apply to the kind of input or output, which would
+/// otherwise be ignored without a word.
+fn conflicting_flags(args: &Cli) -> Option<&'static str> {
+    let input = &args.input_texture;
+    let is_tileset = input.is_dir() || has_extension(input, "xml");
+    let is_image = !is_tileset && !has_extension(input, "txt") && !has_extension(input, "vox");
+    if args.cube && !is_image {
+        return Some("--cube needs an image sample");
+    }
+    if args.tile_size.is_some() && !is_image {
+        return Some("--tile-size needs an image sample");
+    }
+
+    let writes_text = args
+        .output_texture
+        .as_deref()
+        .is_none_or(|path| has_extension(path, "txt"));
+    if args.legend.is_some() && writes_text {
+        return Some("--legend only applies when drawing a .txt sample's output as an image");
+    }
+
+    None
+}
+
+/// Generates an image from the tileset at `args.input_texture`, which is
+/// either described in XML or a directory of tiles.
+fn generate_tiled(args: &Cli) -> Result<image::RgbImage, WfcError> {
+    let model = if args.input_texture.is_dir() {
+        TiledModel::load_dir(&args.input_texture, args.tolerance)?
+    } else {
+        TiledModel::load(&args.input_texture)?
+    };
+    let model = model
+        .with_backtrack_limit(args.backtrack_limit)
+        .with_wrap(args.wrap);
+    let seed = args.seed.unwrap_or_else(rand::random);
+    model.generate(args.width, args.height, seed)
+}
+
+/// Generates an image from the tiles of size `tile_size` in `example`.
+fn generate_from_example(
+    example: &image::RgbImage,
+    tile_size: u32,
+    args: &Cli,
+) -> Result<image::RgbImage, WfcError> {
+    let model = TiledModel::from_example(example, tile_size, args.periodic_input)?
+        .with_backtrack_limit(args.backtrack_limit)
+        .with_wrap(args.wrap);
+    let seed = args.seed.unwrap_or_else(rand::random);
+    model.generate(args.width, args.height, seed)
+}
+
+/// Generates a voxel model from the sample at `args.input_texture`, where
+/// the output is `args.height` voxels tall.
+fn generate_voxels(args: &Cli) -> Result<VoxelGrid, WfcError> {
+    let sample = VoxelGrid::load(&args.input_texture)?;
+    let model = VoxelModel::new(&sample, args.size, args.periodic_input)?
+        .with_backtrack_limit(args.backtrack_limit)
+        .with_wrap(args.wrap);
+    let seed = args.seed.unwrap_or_else(rand::random);
+    model.generate((args.width, args.depth, args.height), seed)
+}
+
+/// Generates a map from the text grid at `args.input_texture`, where each
+/// character is a cell.
+fn generate_text(args: &Cli) -> Result<Texture<char>, WfcError> {
+    let sample = Texture::load(&args.input_texture)?;
+    generate_texture(&sample, config(args))
+}
+
+/// Writes a map generated from a text grid to `args.output_texture`, as
+/// text or drawn with the colors of `args.legend`, or prints it if there's
+/// no output path.
+fn save_text(output: &Texture<char>, args: &Cli) -> Result<(), WfcError> {
+    let Some(path) = &args.output_texture else {
+        print!("{}", output.to_text());
+        return Ok(());
+    };
+    if path.extension().is_some_and(|ext| ext == "txt") {
+        return output.save(path);
+    }
+
+    let Some(legend) = &args.legend else {
+        return Err(WfcError::InvalidLegend {
+            message: "drawing the output as an image needs a --legend".to_string(),
+        });
+    };
+    let image = Legend::load(legend)?.render(output)?;
+    image.save(path).map_err(|err| WfcError::Io {
+        path: path.clone(),
+        message: err.to_string(),
+    })
+}
+
+fn main() -> ExitCode {
     let args = Cli::parse();
-    let image = image::open(&args.input_texture)?.to_rgb8();
+    if let Some(message) = conflicting_flags(&args) {
+        eprintln!("error: {message}");
+        return ExitCode::from(EXIT_INVALID_ARGS);
+    }
+
+    if has_extension(&args.input_texture, "vox") {
+        // Voxel models can't be printed, so there'd be nothing to show for
+        // the generation.
+        let Some(path) = &args.output_texture else {
+            eprintln!("error: generating from a .vox sample needs an --output path");
+            return ExitCode::from(EXIT_INVALID_ARGS);
+        };
+
+        let result = generate_voxels(&args).and_then(|output| output.save(path));
+        if let Err(err) = result {
+            eprintln!("error: {err}");
+            return ExitCode::from(exit_code(&err));
+        }
+
+        return ExitCode::SUCCESS;
+    }
+    if has_extension(&args.input_texture, "txt") {
+        let result = generate_text(&args).and_then(|output| save_text(&output, &args));
+        if let Err(err) = result {
+            eprintln!("error: {err}");
+            return ExitCode::from(exit_code(&err));
+        }
+
+        return ExitCode::SUCCESS;
+    }
+    let is_tileset = args.input_texture.is_dir() || has_extension(&args.input_texture, "xml");
+    let output = if is_tileset {
+        generate_tiled(&args)
+    } else {
+        let image = match image::open(&args.input_texture) {
+            Ok(image) => image.to_rgb8(),
+            Err(err) => {
+                eprintln!("error: {}: {err}", args.input_texture.display());
+                return ExitCode::from(EXIT_IO);
+            }
+        };
+
+        let cfg = config(&args);
+        if let Some(tile_size) = args.tile_size {
+            generate_from_example(&image, tile_size, &args)
+        } else if args.cube {
+            generate_cube(image, cfg).map(|cube| cube.to_cross(image::Rgb([0, 0, 0])).to_image())
+        } else {
+            generate(image, cfg)
+        }
+    };
 
-    let output = generate(
-        image,
-        Config {
-            pattern_size: args.size,
-            width: args.width,
-            height: args.height,
-        },
-    );
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::from(exit_code(&err));
+        }
+    };
 
     if let Some(path) = args.output_texture {
-        output.save(path)?;
+        if let Err(err) = output.save(&path) {
+            eprintln!("error: {}: {err}", path.display());
+            return ExitCode::from(EXIT_IO);
+        }
     }
 
-    Ok(())
+    ExitCode::SUCCESS
 }
@@ -1,168 +1,177 @@
-use std::collections::HashSet;
+use std::collections::hash_map::Entry;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
 use std::{fmt::Debug, ops::Index};
 
-use image::Rgb;
+use rustc_hash::FxHashMap as HashMap;
 
 use crate::direction::Direction;
-use crate::Image;
-
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
-}
-
-impl Debug for Color {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "rgb({}, {}, {})", self.r, self.g, self.b)
-    }
-}
-
-impl Color {
-    pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Color { r, g, b }
-    }
-
-    pub fn to_slice(&self) -> [u8; 3] {
-        [self.r, self.g, self.b]
-    }
-}
-
-impl From<Rgb<u8>> for Color {
-    fn from(value: Rgb<u8>) -> Self {
-        Color::new(value[0], value[1], value[2])
-    }
-}
+use crate::error::WfcError;
+use crate::table::Table;
+use crate::texture::{CellValue, Texture};
 
 #[derive(Clone)]
-pub struct Pattern<'p> {
-    /// The input texture.
-    texture: &'p Image,
+pub struct Pattern<T> {
     /// The pattern ID.
     pub id: usize,
-    pub pixels: Vec<Color>,
+    /// The cells of the pattern, column by column.
+    pub cells: Vec<T>,
     pub size: usize,
+    /// The amount of times the pattern appears in the texture.
+    pub frequency: usize,
 }
 
-impl<'p> Pattern<'p> {
-    pub fn new(id: usize, size: usize, texture: &'p Image, pos: (u32, u32)) -> Self {
-        let pixels = Vec::with_capacity(size * size);
-
-        Pattern {
-            id,
-            texture,
-            pixels,
-            size,
-        }
-        .from_pos(pos)
-    }
-
+impl<T: CellValue> Pattern<T> {
     /// Creates a pattern from a position in the texture.
     ///
-    /// This means taking a square of pixels from the texture, starting at the
+    /// This means taking a square of cells from the texture, starting at the
     /// given position, and adding them to the pattern. The starting position
-    /// is the top-left corner of the square.
-    fn from_pos(mut self, pos: (u32, u32)) -> Self {
-        for dx in 0..self.size {
-            for dy in 0..self.size {
-                let x = pos.0.wrapping_add(dx as u32) % self.texture.width();
-                let y = pos.1.wrapping_add(dy as u32) % self.texture.height();
-
-                let pixel = self.texture[(x, y)];
-                self.pixels.push(pixel.into());
+    /// is the top-left corner of the square, and the square wraps around the
+    /// borders of the texture.
+    pub fn new(id: usize, size: usize, texture: &Texture<T>, pos: (usize, usize)) -> Self {
+        let mut cells = Vec::with_capacity(size * size);
+        for dx in 0..size {
+            for dy in 0..size {
+                let x = (pos.0 + dx) % texture.width();
+                let y = (pos.1 + dy) % texture.height();
+                cells.push(texture[(x, y)]);
             }
         }
 
-        self
+        Pattern {
+            id,
+            cells,
+            size,
+            frequency: 1,
+        }
     }
 
-    /// Returns the pixels of the pattern that constitute the side in the given
+    /// Returns the cells of the pattern that constitute the side in the given
     /// direction.
     ///
-    /// This means that the pixels returned are the ones that are on the side
+    /// This means that the cells returned are the ones that are on the side
     /// of the pattern that is facing the given direction. For example, if the
-    /// direction is `Up`, then the pixels returned are all the pixels except
+    /// direction is `Up`, then the cells returned are all the cells except
     /// the bottom row.
-    pub fn get_side(&self, direction: &Direction) -> Vec<Color> {
-        let mut pixels = Vec::with_capacity(self.size * (self.size - 1));
+    pub fn get_side(&self, direction: &Direction) -> Vec<T> {
+        let mut cells = Vec::with_capacity(self.size * (self.size - 1));
         match direction {
             Direction::Up => {
                 for x in 0..self.size - 1 {
                     for y in 0..self.size {
-                        let pixel = self[(x, y)];
-                        pixels.push(pixel.into());
+                        cells.push(self[(x, y)]);
                     }
                 }
             }
             Direction::Right => {
                 for x in 0..self.size {
                     for y in 1..self.size {
-                        let pixel = self[(x, y)];
-                        pixels.push(pixel.into());
+                        cells.push(self[(x, y)]);
                     }
                 }
             }
             Direction::Down => {
                 for x in 1..self.size {
                     for y in 0..self.size {
-                        let pixel = self[(x, y)];
-                        pixels.push(pixel.into());
+                        cells.push(self[(x, y)]);
                     }
                 }
             }
             Direction::Left => {
                 for x in 0..self.size {
                     for y in 0..self.size - 1 {
-                        let pixel = self[(x, y)];
-                        pixels.push(pixel.into());
+                        cells.push(self[(x, y)]);
                     }
                 }
             }
         }
 
-        pixels
+        cells
     }
 
     /// Checks whether the pattern overlaps with the given pattern in the given
     /// direction.
     ///
     /// This answers whether a given pattern can be put next to another pattern.
-    pub fn overlaps(&self, p2: &Pattern, direction: &Direction) -> bool {
+    pub fn overlaps(&self, p2: &Pattern<T>, direction: &Direction) -> bool {
         let side1 = self.get_side(direction);
         let side2 = p2.get_side(&direction.opposite());
 
         side1 == side2
     }
+
+    /// Returns a copy of the pattern rotated by 90 degrees.
+    pub fn rotate(&self) -> Self {
+        self.transform(|x, y| (self.size - 1 - y, x))
+    }
+
+    /// Returns a copy of the pattern mirrored along the `x` axis.
+    pub fn reflect(&self) -> Self {
+        self.transform(|x, y| (self.size - 1 - x, y))
+    }
+
+    /// Returns the first `symmetry` dihedral variants of the pattern,
+    /// starting with the pattern itself.
+    ///
+    /// The variants alternate between rotations and their reflections, so
+    /// a symmetry of `2` adds the reflection and a symmetry of `8` adds
+    /// every rotation and reflection.
+    pub fn variants(&self, symmetry: usize) -> Vec<Self> {
+        let mut variants = Vec::with_capacity(8);
+        let mut rotated = self.clone();
+        for _ in 0..4 {
+            let reflected = rotated.reflect();
+            let next = rotated.rotate();
+            variants.push(rotated);
+            variants.push(reflected);
+            rotated = next;
+        }
+
+        variants.truncate(symmetry);
+        variants
+    }
+
+    /// Builds a new pattern where the cell at `(x, y)` is taken from the
+    /// position `f(x, y)` of this pattern.
+    fn transform(&self, f: impl Fn(usize, usize) -> (usize, usize)) -> Self {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for x in 0..self.size {
+            for y in 0..self.size {
+                cells.push(self[f(x, y)]);
+            }
+        }
+
+        Pattern {
+            cells,
+            ..self.clone()
+        }
+    }
 }
 
-impl Hash for Pattern<'_> {
+impl<T: Hash> Hash for Pattern<T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.pixels.hash(state);
+        self.cells.hash(state);
     }
 }
 
-impl PartialEq for Pattern<'_> {
+impl<T: PartialEq> PartialEq for Pattern<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.pixels == other.pixels
+        self.cells == other.cells
     }
 }
 
-impl Eq for Pattern<'_> {}
+impl<T: Eq> Eq for Pattern<T> {}
 
-impl Debug for Pattern<'_> {
+impl<T: Debug> Debug for Pattern<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[")?;
-        for i in 0..self.pixels.len() {
+        for i in 0..self.cells.len() {
             if i % self.size == 0 && i != 0 {
-                write!(f, "\n")?;
+                writeln!(f)?;
             }
 
             let idx = (i % self.size) * self.size + i / self.size;
-            let pixel = self.pixels[idx];
-            write!(f, "{:?}", pixel)?;
+            write!(f, "{:?}", self.cells[idx])?;
         }
         write!(f, "]")?;
 
@@ -170,64 +179,192 @@ impl Debug for Pattern<'_> {
     }
 }
 
-impl Display for Pattern<'_> {
+impl<T: Debug> Display for Pattern<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[")?;
-        for i in 0..self.pixels.len() {
-            if i % self.size == 0 && i != 0 {
-                write!(f, "\n")?;
-            }
+        Debug::fmt(self, f)
+    }
+}
 
-            let idx = (i % self.size) * self.size + i / self.size;
-            let pixel = self.pixels[idx];
-            write!(f, "{:?}", pixel)?;
-        }
-        write!(f, "]")?;
+impl<T> Index<(usize, usize)> for Pattern<T> {
+    type Output = T;
 
-        Ok(())
+    fn index(&self, index: (usize, usize)) -> &Self::Output {
+        let i = index.0 * self.size + index.1;
+        &self.cells[i]
     }
 }
 
-impl Index<(u32, u32)> for Pattern<'_> {
-    type Output = Color;
+/// Extracts every distinct pattern of the given size from the texture.
+///
+/// If `periodic` is true, the texture is treated as if it wrapped around its
+/// borders, so there are patterns starting at every cell. Otherwise, only
+/// the patterns that fit entirely inside the texture are extracted.
+///
+/// Patterns are returned in the order they are first found, so the same
+/// texture always yields the same pattern ids. Each pattern keeps track of
+/// how many times it was found.
+pub fn get_patterns<T: CellValue>(
+    texture: &Texture<T>,
+    size: usize,
+    periodic: bool,
+) -> Vec<Pattern<T>> {
+    let (width, height) = windows(texture, size, periodic);
+    dedup((0..width).flat_map(|x| column(texture, size, x, height))).0
+}
 
-    fn index(&self, index: (u32, u32)) -> &Self::Output {
-        let i = index.0 as usize * self.size + index.1 as usize;
-        &self.pixels[i]
-    }
+/// Like [`get_patterns`], but extracts the patterns of each column in
+/// parallel, yielding the same patterns with the same ids.
+#[cfg(feature = "parallel")]
+pub fn par_get_patterns<T: CellValue + Send + Sync>(
+    texture: &Texture<T>,
+    size: usize,
+    periodic: bool,
+) -> Vec<Pattern<T>> {
+    use rayon::prelude::*;
+
+    let (width, height) = windows(texture, size, periodic);
+
+    // Columns are collected in order, so the ids don't depend on how the
+    // extraction was scheduled.
+    let columns: Vec<Vec<Pattern<T>>> = (0..width)
+        .into_par_iter()
+        .map(|x| column(texture, size, x, height).collect())
+        .collect();
+
+    dedup(columns.into_iter().flatten()).0
 }
 
-impl Index<(usize, usize)> for Pattern<'_> {
-    type Output = Color;
+/// The patterns starting in the column `x` of the texture, in the first
+/// `height` rows.
+fn column<T: CellValue>(
+    texture: &Texture<T>,
+    size: usize,
+    x: usize,
+    height: usize,
+) -> impl Iterator<Item = Pattern<T>> + '_ {
+    (0..height).map(move |y| Pattern::new(0, size, texture, (x, y)))
+}
 
-    fn index(&self, index: (usize, usize)) -> &Self::Output {
-        let i = index.0 * self.size + index.1;
-        &self.pixels[i]
+/// The amount of positions a pattern of the given size can start at along
+/// each axis of the texture.
+fn windows<T>(texture: &Texture<T>, size: usize, periodic: bool) -> (usize, usize) {
+    if periodic {
+        texture.dimensions()
+    } else {
+        (
+            (texture.width() + 1).saturating_sub(size),
+            (texture.height() + 1).saturating_sub(size),
+        )
     }
 }
 
-pub fn get_patterns(image: &Image, size: usize) -> HashSet<Pattern> {
-    let mut patterns = HashSet::with_capacity(size * size);
+/// Slices the texture into a grid of `size`x`size` tiles.
+///
+/// Returns the distinct tiles, as patterns that keep track of how many
+/// times they were found, and the id of the tile in each cell of the grid.
+/// Like the solver's, the rows of the grid are columns of the texture.
+///
+/// Cells past the last full row or column of tiles are left out.
+pub fn get_tiles<T: CellValue>(
+    texture: &Texture<T>,
+    size: usize,
+) -> (Vec<Pattern<T>>, Table<usize>) {
+    let (width, height) = (texture.width() / size, texture.height() / size);
+    let tiles = (0..width).flat_map(|x| {
+        (0..height).map(move |y| Pattern::new(0, size, texture, (x * size, y * size)))
+    });
+
+    let (tiles, ids) = dedup(tiles);
+    (tiles, Table::new(ids, height))
+}
+
+/// Adds the dihedral variants of every pattern to the pattern set.
+///
+/// The `symmetry` is the amount of variants of each pattern to add,
+/// counting the pattern itself, so it must be either `1`, `2`, `4` or `8`.
+/// See [`Pattern::variants`].
+pub fn augment<T: CellValue>(
+    patterns: Vec<Pattern<T>>,
+    symmetry: usize,
+) -> Result<Vec<Pattern<T>>, WfcError> {
+    if !matches!(symmetry, 1 | 2 | 4 | 8) {
+        return Err(WfcError::InvalidSymmetry { symmetry });
+    }
+
+    let variants = patterns.iter().flat_map(|p| p.variants(symmetry));
+    Ok(dedup(variants).0)
+}
 
-    for x in 0..image.height() {
-        for y in 0..image.width() {
-            let id = patterns.len();
-            let pattern = Pattern::new(id, size, image, (x, y));
-            patterns.insert(pattern);
+/// Merges equal patterns, adding up their frequencies.
+///
+/// Ids are reassigned in the order patterns are first found. Also returns
+/// the new id of every pattern, in the order they were given.
+fn dedup<T: CellValue>(
+    patterns: impl IntoIterator<Item = Pattern<T>>,
+) -> (Vec<Pattern<T>>, Vec<usize>) {
+    let patterns: Vec<Pattern<T>> = patterns.into_iter().collect();
+    let (_, found) = intern(patterns.iter().map(|pattern| &pattern.cells));
+
+    let mut deduped: Vec<Pattern<T>> = Vec::new();
+    for (mut pattern, &id) in patterns.into_iter().zip(&found) {
+        if id == deduped.len() {
+            pattern.id = id;
+            deduped.push(pattern);
+        } else {
+            deduped[id].frequency += pattern.frequency;
         }
     }
 
-    patterns
+    (deduped, found)
+}
+
+/// Merges equal values, giving each distinct value an id in the order they
+/// are first found.
+///
+/// Returns the distinct values and the id of every value, in the order
+/// they were given.
+pub(crate) fn intern<V: Eq + Hash + Clone>(
+    values: impl IntoIterator<Item = V>,
+) -> (Vec<V>, Vec<usize>) {
+    let mut ids: HashMap<V, usize> = HashMap::default();
+    let mut distinct = Vec::new();
+    let found = values
+        .into_iter()
+        .map(|value| match ids.entry(value) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                distinct.push(entry.key().clone());
+                *entry.insert(distinct.len() - 1)
+            }
+        })
+        .collect();
+
+    (distinct, found)
+}
+
+/// Like [`intern`], but returns how many times each distinct value was
+/// found instead of the id of every value.
+pub(crate) fn count<V: Eq + Hash + Clone>(
+    values: impl IntoIterator<Item = V>,
+) -> (Vec<V>, Vec<usize>) {
+    let (distinct, found) = intern(values);
+    let mut frequencies = vec![0; distinct.len()];
+    for id in found {
+        frequencies[id] += 1;
+    }
+
+    (distinct, frequencies)
 }
 
 #[cfg(test)]
 mod tests {
-    use image::{Rgb, RgbImage};
     use pretty_assertions::assert_eq;
 
     use crate::{
         direction::Direction,
+        error::WfcError,
         test_utils::{c, img, p},
+        texture::Texture,
     };
 
     #[test]
@@ -236,12 +373,12 @@ mod tests {
         // [2, 3]
         let texture = img(2);
         let pattern = p(0, 2, &texture, (0, 0));
-        assert_eq!(pattern.pixels.len(), 4);
-        assert_eq!(pattern.pixels, vec![c(0), c(1), c(2), c(3)]);
+        assert_eq!(pattern.cells.len(), 4);
+        assert_eq!(pattern.cells, vec![c(0), c(1), c(2), c(3)]);
 
         let pattern = p(1, 2, &texture, (0, 1));
-        assert_eq!(pattern.pixels.len(), 4);
-        assert_eq!(pattern.pixels, vec![c(1), c(0), c(3), c(2)]);
+        assert_eq!(pattern.cells.len(), 4);
+        assert_eq!(pattern.cells, vec![c(1), c(0), c(3), c(2)]);
 
         // [0, 1, 2, 3]
         // [4, 5, 6, 7]
@@ -249,27 +386,27 @@ mod tests {
         // [12, 13, 14, 15]
         let texture = img(4);
         let pattern = p(0, 2, &texture, (0, 0));
-        assert_eq!(pattern.pixels.len(), 4);
-        assert_eq!(pattern.pixels, vec![c(0), c(1), c(4), c(5)]);
+        assert_eq!(pattern.cells.len(), 4);
+        assert_eq!(pattern.cells, vec![c(0), c(1), c(4), c(5)]);
 
         let pattern = p(1, 2, &texture, (0, 1));
-        assert_eq!(pattern.pixels.len(), 4);
-        assert_eq!(pattern.pixels, vec![c(1), c(2), c(5), c(6)]);
+        assert_eq!(pattern.cells.len(), 4);
+        assert_eq!(pattern.cells, vec![c(1), c(2), c(5), c(6)]);
 
         let pattern = p(2, 3, &texture, (3, 3));
-        assert_eq!(pattern.pixels.len(), 9);
+        assert_eq!(pattern.cells.len(), 9);
         assert_eq!(
-            pattern.pixels,
+            pattern.cells,
             vec![c(15), c(12), c(13), c(3), c(0), c(1), c(7), c(4), c(5)]
         );
 
         let pattern = p(3, 1, &texture, (0, 0));
-        assert_eq!(pattern.pixels.len(), 1);
-        assert_eq!(pattern.pixels, vec![c(0)]);
+        assert_eq!(pattern.cells.len(), 1);
+        assert_eq!(pattern.cells, vec![c(0)]);
 
         let pattern = p(4, 2, &texture, (3, 1));
-        assert_eq!(pattern.pixels.len(), 4);
-        assert_eq!(pattern.pixels, vec![c(13), c(14), c(1), c(2)]);
+        assert_eq!(pattern.cells.len(), 4);
+        assert_eq!(pattern.cells, vec![c(13), c(14), c(1), c(2)]);
     }
 
     #[test]
@@ -280,7 +417,7 @@ mod tests {
         // [12, 13, 14, 15]
         let texture = img(4);
         let pattern = p(0, 2, &texture, (0, 0));
-        assert_eq!(pattern.pixels, vec![c(0), c(1), c(4), c(5)]);
+        assert_eq!(pattern.cells, vec![c(0), c(1), c(4), c(5)]);
         assert_eq!(pattern.get_side(&Direction::Up), vec![c(0), c(1)]);
         assert_eq!(pattern.get_side(&Direction::Right), vec![c(1), c(5)]);
         assert_eq!(pattern.get_side(&Direction::Down), vec![c(4), c(5)]);
@@ -288,7 +425,7 @@ mod tests {
 
         let pattern = p(1, 3, &texture, (3, 3));
         assert_eq!(
-            pattern.pixels,
+            pattern.cells,
             vec![c(15), c(12), c(13), c(3), c(0), c(1), c(7), c(4), c(5)]
         );
         assert_eq!(
@@ -315,12 +452,7 @@ mod tests {
         // [1, 2, 3, 4]
         // [2, 3, 4, 5]
         // [3, 4, 5, 6]
-        let mut texture = RgbImage::new(4, 4);
-        for x in 0..4 {
-            for y in 0..4 {
-                texture.put_pixel(x, y, Rgb([(x + y) as u8, 0, 0]));
-            }
-        }
+        let texture = Texture::from_fn(4, 4, |x, y| c((x + y) as u8));
 
         let p1 = p(0, 2, &texture, (0, 0));
         let p2 = p(1, 2, &texture, (1, 0));
@@ -364,7 +496,7 @@ mod tests {
         // [3, 4, 5]
         // [6, 7, 8]
         let texture = img(3);
-        let patterns = super::get_patterns(&texture, 2);
+        let patterns = super::get_patterns(&texture, 2, true);
         assert_eq!(patterns.len(), 9);
         let expected = vec![
             p(0, 2, &texture, (0, 0)),
@@ -384,21 +516,17 @@ mod tests {
         // [0, 0, 0]
         // [0, 0, 0]
         // [0, 0, 0]
-        let mut texture = RgbImage::new(3, 3);
-        for x in 0..3 {
-            for y in 0..3 {
-                texture.put_pixel(x, y, Rgb([0, 0, 0]));
-            }
-        }
-        let patterns = super::get_patterns(&texture, 2);
+        let texture = Texture::from_fn(3, 3, |_, _| c(0));
+        let patterns = super::get_patterns(&texture, 2, true);
         assert_eq!(patterns.len(), 1);
         assert!(patterns.contains(&p(0, 2, &texture, (0, 0))));
+        assert_eq!(patterns[0].frequency, 9);
 
         // [0, 1, 2]
         // [3, 4, 5]
         // [6, 7, 8]
         let texture = img(3);
-        let patterns = super::get_patterns(&texture, 3);
+        let patterns = super::get_patterns(&texture, 3, true);
         assert_eq!(patterns.len(), 9);
         let expected = vec![
             p(0, 3, &texture, (0, 0)),
@@ -420,7 +548,7 @@ mod tests {
         // [8, 9, 10, 11]
         // [12, 13, 14, 15]
         let texture = img(4);
-        let patterns = super::get_patterns(&texture, 3);
+        let patterns = super::get_patterns(&texture, 3, true);
         assert_eq!(patterns.len(), 16);
         let expected = vec![
             p(0, 3, &texture, (0, 0)),
@@ -444,4 +572,156 @@ mod tests {
             assert!(patterns.contains(&pattern));
         }
     }
+
+    #[test]
+    fn get_patterns_frequency() {
+        // [0, 0, 0, 0]
+        // [0, 0, 0, 0]
+        // [0, 0, 0, 0]
+        // [0, 0, 0, 1]
+        let mut texture = Texture::from_fn(4, 4, |_, _| c(0));
+        texture[(3, 3)] = c(1);
+
+        let patterns = super::get_patterns(&texture, 2, true);
+        assert_eq!(patterns.len(), 5);
+        assert_eq!(patterns.iter().map(|p| p.frequency).sum::<usize>(), 16);
+        assert_eq!(patterns[0], p(0, 2, &texture, (0, 0)));
+        assert_eq!(patterns[0].frequency, 12);
+        assert!(patterns[1..].iter().all(|p| p.frequency == 1));
+    }
+
+    #[test]
+    fn transforms() {
+        // [0, 1]
+        // [2, 3]
+        let texture = img(2);
+        let pattern = p(0, 2, &texture, (0, 0));
+        assert_eq!(pattern.rotate().cells, vec![c(2), c(0), c(3), c(1)]);
+        assert_eq!(pattern.reflect().cells, vec![c(2), c(3), c(0), c(1)]);
+        assert_eq!(pattern.rotate().rotate().rotate().rotate(), pattern);
+        assert_eq!(pattern.reflect().reflect(), pattern);
+
+        // [0, 1, 2]
+        // [3, 4, 5]
+        // [6, 7, 8]
+        let texture = img(3);
+        let pattern = p(0, 3, &texture, (0, 0));
+        assert_eq!(
+            pattern.rotate().cells,
+            vec![c(6), c(3), c(0), c(7), c(4), c(1), c(8), c(5), c(2)]
+        );
+        assert_eq!(
+            pattern.reflect().cells,
+            vec![c(6), c(7), c(8), c(3), c(4), c(5), c(0), c(1), c(2)]
+        );
+
+        let variants = pattern.variants(8);
+        assert_eq!(variants.len(), 8);
+        assert_eq!(variants[0], pattern);
+        assert_eq!(variants[1], pattern.reflect());
+        assert_eq!(variants[2], pattern.rotate());
+        assert_eq!(variants[3], pattern.rotate().reflect());
+        for (i, v1) in variants.iter().enumerate() {
+            assert!(variants[i + 1..].iter().all(|v2| v1 != v2));
+        }
+        assert_eq!(pattern.variants(2), variants[..2]);
+    }
+
+    #[test]
+    fn augment() {
+        // [0, 1, 2]
+        // [3, 4, 5]
+        // [6, 7, 8]
+        let texture = img(3);
+        let patterns = vec![p(0, 3, &texture, (0, 0))];
+        let augmented = super::augment(patterns.clone(), 8).unwrap();
+        assert_eq!(augmented.len(), 8);
+        assert_eq!(
+            augmented.iter().map(|p| p.id).collect::<Vec<_>>(),
+            (0..8).collect::<Vec<_>>()
+        );
+
+        let augmented = super::augment(patterns.clone(), 1).unwrap();
+        assert_eq!(augmented, patterns);
+
+        // Every variant of a uniform pattern is the same pattern.
+        let texture = Texture::from_fn(2, 2, |_, _| c(0));
+        let patterns = super::get_patterns(&texture, 2, true);
+        let augmented = super::augment(patterns, 8).unwrap();
+        assert_eq!(augmented.len(), 1);
+        assert_eq!(augmented[0].frequency, 32);
+
+        assert_eq!(
+            super::augment::<u8>(vec![], 3),
+            Err(WfcError::InvalidSymmetry { symmetry: 3 })
+        );
+    }
+
+    #[test]
+    fn get_tiles() {
+        // [0, 1, 0, 1, 4]
+        // [0, 1, 0, 1, 4]
+        // [2, 3, 0, 1, 4]
+        // [2, 3, 0, 1, 4]
+        let texture = Texture::from_fn(5, 4, |x, y| match (x, y) {
+            (4, _) => c(4),
+            (0 | 1, 2 | 3) => c(2 + x as u8 % 2),
+            _ => c(x as u8 % 2),
+        });
+
+        let (tiles, grid) = super::get_tiles(&texture, 2);
+        assert_eq!(tiles.len(), 2);
+        assert_eq!(tiles[0].cells, vec![c(0), c(0), c(1), c(1)]);
+        assert_eq!(tiles[0].frequency, 3);
+        assert_eq!(tiles[1].cells, vec![c(2), c(2), c(3), c(3)]);
+        assert_eq!(tiles[1].frequency, 1);
+
+        // The last column doesn't make up a full tile.
+        assert_eq!(grid.len(), 4);
+        assert_eq!(grid[(0, 0)], 0);
+        assert_eq!(grid[(0, 1)], 1);
+        assert_eq!(grid[(1, 0)], 0);
+        assert_eq!(grid[(1, 1)], 0);
+    }
+
+    #[test]
+    fn get_patterns_non_periodic() {
+        // [0, 1, 2]
+        // [3, 4, 5]
+        // [6, 7, 8]
+        let texture = img(3);
+        let patterns = super::get_patterns(&texture, 2, false);
+        assert_eq!(patterns.len(), 4);
+        let expected = vec![
+            p(0, 2, &texture, (0, 0)),
+            p(1, 2, &texture, (0, 1)),
+            p(2, 2, &texture, (1, 0)),
+            p(3, 2, &texture, (1, 1)),
+        ];
+        assert_eq!(patterns, expected);
+
+        let patterns = super::get_patterns(&texture, 3, false);
+        assert_eq!(patterns, vec![p(0, 3, &texture, (0, 0))]);
+
+        // Patterns are taken along both axes of non-square images.
+        let mut texture = Texture::from_fn(4, 2, |_, _| c(0));
+        texture[(3, 1)] = c(1);
+        let patterns = super::get_patterns(&texture, 2, false);
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[0].frequency, 2);
+        assert_eq!(patterns[1], p(1, 2, &texture, (2, 0)));
+
+        let patterns = super::get_patterns(&texture, 2, true);
+        assert_eq!(patterns.iter().map(|p| p.frequency).sum::<usize>(), 8);
+    }
+
+    #[test]
+    fn intern_and_count() {
+        let values = ["b", "a", "b", "c", "b"];
+        assert_eq!(
+            super::intern(values),
+            (vec!["b", "a", "c"], vec![0, 1, 0, 2, 0])
+        );
+        assert_eq!(super::count(values), (vec!["b", "a", "c"], vec![3, 1, 1]));
+    }
 }
@@ -0,0 +1,316 @@
+use std::hash::Hash;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::compatibility::Compatibility;
+use crate::direction::Direction1;
+use crate::error::WfcError;
+use crate::pattern;
+use crate::topology::Topology;
+use crate::wfc::{solve_graph, Graph, DEFAULT_BACKTRACK_LIMIT};
+
+/// The overlapping model in one dimension.
+///
+/// It extracts every window of `N` consecutive elements of a sample
+/// sequence, such as the notes of a melody or the characters of a line of
+/// text, and generates sequences whose windows all appear in the sample.
+pub struct SequenceModel<T> {
+    /// The elements of each window.
+    patterns: Vec<Vec<T>>,
+    /// The amount of times each window appears in the sample.
+    frequencies: Vec<usize>,
+    /// The windows that overlap each window in each direction.
+    compatibility: Compatibility,
+    /// The maximum amount of times the solver may backtrack in a single
+    /// generation.
+    backtrack_limit: usize,
+    /// Whether the output wraps around, so that its last element comes
+    /// right before its first one.
+    wrap: bool,
+}
+
+impl<T: Clone + Eq + Hash> SequenceModel<T> {
+    /// Extracts the windows of `window` elements from the sample, which
+    /// wraps around its ends if `periodic_input` is set.
+    pub fn new(sample: &[T], window: usize, periodic_input: bool) -> Result<Self, WfcError> {
+        if window == 0 {
+            return Err(WfcError::ZeroPatternSize);
+        }
+
+        if !periodic_input && window > sample.len() {
+            return Err(WfcError::PatternTooLarge {
+                pattern_size: window,
+                width: sample.len(),
+                height: 1,
+            });
+        }
+
+        let starts = if periodic_input {
+            0..sample.len()
+        } else {
+            0..sample.len() - window + 1
+        };
+
+        let (patterns, frequencies) = pattern::count(starts.map(|start| {
+            (start..start + window)
+                .map(|i| sample[i % sample.len()].clone())
+                .collect::<Vec<_>>()
+        }));
+
+        let compatibility = build_constraints(&patterns);
+
+        Ok(SequenceModel {
+            patterns,
+            frequencies,
+            compatibility,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            wrap: false,
+        })
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Makes the output wrap around, so that it can be repeated seamlessly.
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// The amount of distinct windows in the sample.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Generates a sequence of `len` elements.
+    ///
+    /// The same seed always produces the same output for the same sample.
+    pub fn generate(&self, len: usize, seed: u64) -> Result<Vec<T>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(len, &mut rng)
+    }
+
+    /// Like [`SequenceModel::generate`], but draws every random choice from
+    /// `rng`.
+    ///
+    /// Contradictions point at the element as the cell `(index, 0)`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        len: usize,
+        rng: &mut R,
+    ) -> Result<Vec<T>, WfcError> {
+        if len == 0 {
+            return Err(WfcError::ZeroOutputSize {
+                width: 0,
+                height: 1,
+            });
+        }
+
+        let cells = Line {
+            len,
+            wrap: self.wrap,
+        };
+        let graph = Graph::new(&cells);
+        let weights = self.frequencies.iter().map(|&f| f as f64).collect();
+        let ids = solve_graph(
+            &self.compatibility,
+            weights,
+            &graph,
+            self.backtrack_limit,
+            rng,
+        )
+        .map_err(|failure| failure.into_error(|slot| (slot, 0)))?;
+
+        Ok(ids
+            .into_iter()
+            .map(|id| self.patterns[id][0].clone())
+            .collect())
+    }
+}
+
+impl SequenceModel<char> {
+    /// Extracts the windows of `window` characters from a line of text.
+    pub fn from_text(text: &str, window: usize, periodic_input: bool) -> Result<Self, WfcError> {
+        let sample = text.chars().collect::<Vec<_>>();
+        SequenceModel::new(&sample, window, periodic_input)
+    }
+
+    /// Like [`SequenceModel::generate`], but collects the characters into a
+    /// string.
+    pub fn generate_text(&self, len: usize, seed: u64) -> Result<String, WfcError> {
+        Ok(self.generate(len, seed)?.into_iter().collect())
+    }
+}
+
+/// The elements of an output sequence, each connected to the previous and
+/// next ones.
+struct Line {
+    len: usize,
+    wrap: bool,
+}
+
+impl Topology for Line {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn directions(&self) -> usize {
+        2
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        Direction1::all()
+            .into_iter()
+            .filter_map(|d| {
+                let neighbor = idx as i32 + d.offset();
+                let neighbor = if self.wrap {
+                    neighbor.rem_euclid(self.len as i32)
+                } else if (0..self.len as i32).contains(&neighbor) {
+                    neighbor
+                } else {
+                    return None;
+                };
+                Some((usize::from(d), neighbor as usize))
+            })
+            .collect()
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        usize::from(Direction1::from(direction).opposite())
+    }
+}
+
+/// Makes two windows compatible when they overlap by all but one element.
+fn build_constraints<T: Eq + Hash>(patterns: &[Vec<T>]) -> Compatibility {
+    Compatibility::overlapping(
+        patterns.iter().enumerate(),
+        &Direction1::all(),
+        |d| d.opposite(),
+        |pattern, d| side(pattern, d),
+    )
+}
+
+/// The elements of a window shared with its neighbor in the given
+/// direction.
+fn side<T>(pattern: &[T], direction: Direction1) -> &[T] {
+    match direction {
+        Direction1::Previous => &pattern[..pattern.len() - 1],
+        Direction1::Next => &pattern[1..],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::SequenceModel;
+    use crate::direction::Direction1;
+    use crate::error::WfcError;
+    use crate::test_utils::assert_deterministic;
+
+    #[test]
+    fn patterns() {
+        let model = SequenceModel::new(&[1, 2, 1, 2, 3], 2, false).unwrap();
+        assert_eq!(model.patterns, vec![vec![1, 2], vec![2, 1], vec![2, 3]]);
+        assert_eq!(model.frequencies, vec![2, 1, 1]);
+
+        let compatible = |p1, d| model.compatibility.compatible(p1, d).collect_vec();
+        assert_eq!(compatible(0, Direction1::Next), vec![1, 2]);
+        assert_eq!(compatible(0, Direction1::Previous), vec![1]);
+        assert!(compatible(2, Direction1::Next).is_empty());
+
+        // The last window wraps around to the first element.
+        let model = SequenceModel::new(&[1, 2, 1, 2, 3], 2, true).unwrap();
+        assert_eq!(model.len(), 4);
+        assert_eq!(model.patterns[3], vec![3, 1]);
+    }
+
+    #[test]
+    fn generate() {
+        let sample = [0, 0, 1, 2, 0, 1, 1, 2, 2, 0];
+        let model = SequenceModel::new(&sample, 3, true).unwrap();
+        let windows = (0..sample.len())
+            .map(|i| (0..3).map(|k| sample[(i + k) % sample.len()]).collect_vec())
+            .collect_vec();
+
+        for output in assert_deterministic(|seed| model.generate(30, seed)) {
+            assert_eq!(output.len(), 30);
+            for window in output.windows(3) {
+                assert!(windows.contains(&window.to_vec()), "{window:?}");
+            }
+        }
+
+        // Wrapping around keeps the windows across the ends in the sample.
+        let model = model.with_wrap(true);
+        let output = model.generate(20, 0).unwrap();
+        for i in 0..20 {
+            let window = (0..3).map(|k| output[(i + k) % 20]).collect_vec();
+            assert!(windows.contains(&window), "{window:?}");
+        }
+    }
+
+    #[test]
+    fn text() {
+        let model = SequenceModel::from_text("abcabd", 2, true).unwrap();
+        for seed in 0..4 {
+            let text = model.generate_text(12, seed).unwrap();
+            assert_eq!(text.chars().count(), 12);
+            for (a, b) in text.chars().tuple_windows() {
+                assert!("abcabda".contains(&format!("{a}{b}")), "{a}{b}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            SequenceModel::new(&[1, 2], 0, true).err(),
+            Some(WfcError::ZeroPatternSize)
+        );
+        assert_eq!(
+            SequenceModel::new(&[1, 2], 3, false).err(),
+            Some(WfcError::PatternTooLarge {
+                pattern_size: 3,
+                width: 2,
+                height: 1
+            })
+        );
+
+        let model = SequenceModel::new(&[1, 2, 3], 2, false).unwrap();
+        assert_eq!(
+            model.generate(0, 0),
+            Err(WfcError::ZeroOutputSize {
+                width: 0,
+                height: 1
+            })
+        );
+
+        // Nothing comes after `[2, 3]`, or before `[1, 2]`, so only two
+        // elements fit in a row.
+        assert!(model.generate(2, 0).is_ok());
+        assert!(matches!(
+            model.generate(3, 0),
+            Err(WfcError::Contradiction {
+                observations: 0,
+                ..
+            })
+        ));
+        assert!(model.with_wrap(true).generate(2, 0).is_err());
+
+        assert_eq!(
+            SequenceModel::<u8>::new(&[], 2, true)
+                .unwrap()
+                .generate(3, 0),
+            Err(WfcError::NoPatterns)
+        );
+    }
+}
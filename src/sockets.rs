@@ -0,0 +1,414 @@
+use std::path::Path;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rustc_hash::FxHashSet as HashSet;
+use serde::Deserialize;
+
+use crate::compatibility::Compatibility;
+use crate::direction::{transpose, Direction};
+use crate::error::WfcError;
+use crate::table::Wrap;
+use crate::tiles::{check_tiles, TileSolver};
+
+/// The labels on the sides of a tile.
+///
+/// Sides are named as seen in the output, so `up` faces the previous row.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Sockets {
+    pub up: String,
+    pub right: String,
+    pub down: String,
+    pub left: String,
+}
+
+impl Sockets {
+    pub fn new(
+        up: impl Into<String>,
+        right: impl Into<String>,
+        down: impl Into<String>,
+        left: impl Into<String>,
+    ) -> Self {
+        Sockets {
+            up: up.into(),
+            right: right.into(),
+            down: down.into(),
+            left: left.into(),
+        }
+    }
+
+    /// The socket on the side of the tile facing the given direction.
+    pub fn get(&self, direction: Direction) -> &str {
+        match direction {
+            Direction::Up => &self.up,
+            Direction::Right => &self.right,
+            Direction::Down => &self.down,
+            Direction::Left => &self.left,
+        }
+    }
+}
+
+/// A tile of the socket model.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SocketTile {
+    pub name: String,
+    pub sockets: Sockets,
+    /// How likely the tile is to be chosen, relative to the other tiles.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+impl SocketTile {
+    pub fn new(name: impl Into<String>, sockets: Sockets) -> Self {
+        SocketTile {
+            name: name.into(),
+            sockets,
+            weight: default_weight(),
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// The tiles of the socket model and the rules to match their sockets.
+///
+/// Two tiles can be put next to each other when the sockets on their
+/// facing sides match. A socket matches an equal socket, unless it's part
+/// of one of the asymmetric `pairs`, in which case it only matches the
+/// sockets it's paired with.
+///
+/// The rules can be read from JSON:
+///
+/// ```json
+/// {
+///   "tiles": [
+///     {
+///       "name": "corridor",
+///       "sockets": { "up": "wall", "right": "door", "down": "wall", "left": "door" },
+///       "weight": 2.0
+///     }
+///   ],
+///   "pairs": [["cliff top", "cliff bottom"]]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct SocketRules {
+    pub tiles: Vec<SocketTile>,
+    #[serde(default)]
+    pub pairs: Vec<(String, String)>,
+}
+
+impl SocketRules {
+    pub fn new() -> Self {
+        SocketRules::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, WfcError> {
+        serde_json::from_str(json).map_err(|err| WfcError::InvalidTileset {
+            message: err.to_string(),
+        })
+    }
+
+    pub fn with_tile(mut self, tile: SocketTile) -> Self {
+        self.tiles.push(tile);
+        self
+    }
+
+    /// Makes the sockets `a` and `b` match each other, and no longer match
+    /// themselves.
+    pub fn with_pair(mut self, a: impl Into<String>, b: impl Into<String>) -> Self {
+        self.pairs.push((a.into(), b.into()));
+        self
+    }
+
+    /// Checks whether socket `a` can face socket `b`.
+    fn matches(&self, paired: &HashSet<&str>, a: &str, b: &str) -> bool {
+        if paired.contains(a) || paired.contains(b) {
+            self.pairs
+                .iter()
+                .any(|(x, y)| (x == a && y == b) || (x == b && y == a))
+        } else {
+            a == b
+        }
+    }
+}
+
+/// The socket model.
+///
+/// It fills a grid with tiles, so that the sockets on the facing sides of
+/// every pair of adjacent tiles match.
+pub struct SocketModel {
+    tiles: Vec<SocketTile>,
+    /// The tiles that can be put next to each tile, and how to fill a grid
+    /// with them.
+    solver: TileSolver,
+}
+
+impl SocketModel {
+    /// Builds the model, failing if two tiles share a name or a weight is
+    /// negative.
+    pub fn new(rules: SocketRules) -> Result<Self, WfcError> {
+        let weights = check_tiles(
+            rules
+                .tiles
+                .iter()
+                .map(|tile| (tile.name.as_str(), tile.weight)),
+        )?;
+
+        let paired: HashSet<&str> = rules
+            .pairs
+            .iter()
+            .flat_map(|(a, b)| [a.as_str(), b.as_str()])
+            .collect();
+        let mut compatibility = Compatibility::new(rules.tiles.len());
+        for (t1, tile) in rules.tiles.iter().enumerate() {
+            for d in Direction::all() {
+                let socket = tile.sockets.get(d);
+                for (t2, other) in rules.tiles.iter().enumerate() {
+                    if rules.matches(&paired, socket, other.sockets.get(d.opposite())) {
+                        compatibility.insert(t1, t2, transpose(d));
+                    }
+                }
+            }
+        }
+
+        Ok(SocketModel {
+            tiles: rules.tiles,
+            solver: TileSolver::new(compatibility, weights),
+        })
+    }
+
+    /// Loads the rules of the model from a JSON file. See [`SocketRules`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+        SocketModel::new(SocketRules::from_json(&json)?)
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.solver.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Sets the axes along which the output wraps around.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.solver.wrap = wrap;
+        self
+    }
+
+    /// The tiles, indexed by the ids used in the output.
+    pub fn tiles(&self) -> &[SocketTile] {
+        &self.tiles
+    }
+
+    /// Generates a grid `width` tiles wide and `height` tiles tall, as rows
+    /// of tile ids.
+    ///
+    /// The same seed always produces the same output for the same rules.
+    pub fn generate(
+        &self,
+        width: usize,
+        height: usize,
+        seed: u64,
+    ) -> Result<Vec<Vec<usize>>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(width, height, &mut rng)
+    }
+
+    /// Like [`SocketModel::generate`], but draws every random choice from
+    /// `rng`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Vec<usize>>, WfcError> {
+        self.solver.generate_rows(width, height, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{SocketModel, SocketRules, SocketTile, Sockets};
+    use crate::direction::Direction;
+    use crate::error::WfcError;
+    use crate::table::Wrap;
+    use crate::test_utils::assert_deterministic;
+
+    /// Pipes that run along rows and columns, with a cross joining them.
+    fn pipes() -> SocketRules {
+        SocketRules::new()
+            .with_tile(SocketTile::new("empty", Sockets::new("-", "-", "-", "-")).with_weight(4.0))
+            .with_tile(SocketTile::new(
+                "row",
+                Sockets::new("-", "pipe", "-", "pipe"),
+            ))
+            .with_tile(SocketTile::new(
+                "column",
+                Sockets::new("pipe", "-", "pipe", "-"),
+            ))
+            .with_tile(SocketTile::new(
+                "cross",
+                Sockets::new("pipe", "pipe", "pipe", "pipe"),
+            ))
+    }
+
+    /// Checks that the sockets of every pair of adjacent tiles match.
+    fn assert_connected(model: &SocketModel, rows: &[Vec<usize>]) {
+        let tiles = model.tiles();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &id) in row.iter().enumerate() {
+                if let Some(&right) = row.get(x + 1) {
+                    assert_eq!(
+                        tiles[id].sockets.get(Direction::Right),
+                        tiles[right].sockets.get(Direction::Left)
+                    );
+                }
+                if let Some(below) = rows.get(y + 1) {
+                    assert_eq!(
+                        tiles[id].sockets.get(Direction::Down),
+                        tiles[below[x]].sockets.get(Direction::Up)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generate() {
+        let model = SocketModel::new(pipes()).unwrap();
+        for rows in assert_deterministic(|seed| model.generate(7, 5, seed)) {
+            assert_eq!(rows.len(), 5);
+            assert!(rows.iter().all(|row| row.len() == 7));
+            assert_connected(&model, &rows);
+        }
+
+        let model = SocketModel::new(pipes()).unwrap().with_wrap(Wrap::Both);
+        let rows = model.generate(6, 6, 0).unwrap();
+        assert_connected(&model, &rows);
+    }
+
+    #[test]
+    fn pairs() {
+        // Cliffs can only be stacked as a top on a bottom.
+        let rules = SocketRules::new()
+            .with_tile(SocketTile::new(
+                "top",
+                Sockets::new("sky", "x", "cliff top", "x"),
+            ))
+            .with_tile(SocketTile::new(
+                "bottom",
+                Sockets::new("cliff bottom", "x", "ground", "x"),
+            ))
+            .with_tile(SocketTile::new("sky", Sockets::new("sky", "x", "sky", "x")))
+            .with_tile(SocketTile::new(
+                "ground",
+                Sockets::new("ground", "x", "ground", "x"),
+            ))
+            .with_pair("cliff top", "cliff bottom");
+        let model = SocketModel::new(rules).unwrap();
+
+        let allowed = [
+            ("sky", "sky"),
+            ("sky", "top"),
+            ("top", "bottom"),
+            ("bottom", "ground"),
+            ("ground", "ground"),
+        ];
+        let mut cliffs = 0;
+        for seed in 0..8 {
+            let rows = model.generate(3, 6, seed).unwrap();
+            let names = |y: usize| rows[y].iter().map(|&id| model.tiles()[id].name.as_str());
+            for (above, below) in (0..6).tuple_windows() {
+                for pair in names(above).zip(names(below)) {
+                    assert!(allowed.contains(&pair), "{pair:?}");
+                    cliffs += usize::from(pair == ("top", "bottom"));
+                }
+            }
+        }
+        assert!(cliffs > 0);
+
+        let compatibility = &model.solver.compatibility;
+        assert!(compatibility.contains(0, 1, super::transpose(Direction::Down)));
+        assert!(!compatibility.contains(1, 0, super::transpose(Direction::Down)));
+
+        // Paired sockets no longer match themselves.
+        let rules = SocketRules::new().with_pair("a", "b");
+        let paired = ["a", "b"].into_iter().collect();
+        assert!(rules.matches(&paired, "a", "b"));
+        assert!(rules.matches(&paired, "b", "a"));
+        assert!(!rules.matches(&paired, "a", "a"));
+        assert!(!rules.matches(&paired, "a", "c"));
+        assert!(rules.matches(&paired, "c", "c"));
+    }
+
+    #[test]
+    fn from_json() {
+        let json = r#"{
+            "tiles": [
+                {
+                    "name": "empty",
+                    "sockets": { "up": "-", "right": "-", "down": "-", "left": "-" },
+                    "weight": 4.0
+                },
+                {
+                    "name": "row",
+                    "sockets": { "up": "-", "right": "pipe", "down": "-", "left": "pipe" }
+                },
+                {
+                    "name": "column",
+                    "sockets": { "up": "pipe", "right": "-", "down": "pipe", "left": "-" },
+                    "weight": 1
+                },
+                {
+                    "name": "cross",
+                    "sockets": { "up": "pipe", "right": "pipe", "down": "pipe", "left": "pipe" }
+                }
+            ]
+        }"#;
+        assert_eq!(SocketRules::from_json(json), Ok(pipes()));
+
+        assert!(matches!(
+            SocketRules::from_json(r#"{ "tiles": [{ "name": "a" }] }"#),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let rules = pipes().with_tile(SocketTile::new("row", Sockets::new("-", "-", "-", "-")));
+        assert!(matches!(
+            SocketModel::new(rules),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+
+        let rules = pipes()
+            .with_tile(SocketTile::new("a", Sockets::new("-", "-", "-", "-")).with_weight(-1.0));
+        assert!(matches!(
+            SocketModel::new(rules),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+
+        assert_eq!(
+            SocketModel::new(SocketRules::new())
+                .unwrap()
+                .generate(2, 2, 0),
+            Err(WfcError::NoPatterns)
+        );
+    }
+}
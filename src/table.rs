@@ -1,19 +1,72 @@
 use std::{
     fmt::Debug,
     ops::{Index, IndexMut},
+    str::FromStr,
 };
 
 use crate::direction::Direction;
+use crate::topology::Topology;
+
+/// The axes along which a table wraps around, joining its opposite borders.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    #[default]
+    None,
+    /// The `x` axis wraps, so the output tiles horizontally.
+    Horizontal,
+    /// The `y` axis wraps, so the output tiles vertically.
+    Vertical,
+    /// Both axes wrap, so the output tiles in every direction.
+    Both,
+}
+
+impl Wrap {
+    pub fn wraps_x(&self) -> bool {
+        matches!(self, Wrap::Horizontal | Wrap::Both)
+    }
+
+    pub fn wraps_y(&self) -> bool {
+        matches!(self, Wrap::Vertical | Wrap::Both)
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Wrap::None),
+            "horizontal" => Ok(Wrap::Horizontal),
+            "vertical" => Ok(Wrap::Vertical),
+            "both" => Ok(Wrap::Both),
+            _ => Err(format!(
+                "expected one of none, horizontal, vertical or both, got {s}"
+            )),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct Table<T> {
     collection: Vec<T>,
     width: usize,
+    wrap: Wrap,
 }
 
 impl<T> Table<T> {
     pub fn new(collection: Vec<T>, width: usize) -> Self {
-        Table { collection, width }
+        Table {
+            collection,
+            width,
+            wrap: Wrap::None,
+        }
+    }
+
+    /// Makes the table wrap around along the given axes, so that cells on
+    /// opposite borders are neighbors.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
     }
 
     pub fn width(&self) -> usize {
@@ -28,24 +81,32 @@ impl<T> Table<T> {
         self.collection.iter()
     }
 
-    pub fn get(&self, (x, y): (usize, usize)) -> &T {
-        self.collection.index(x * self.height() + y)
-    }
-
     pub fn idx_to_pos(&self, idx: usize) -> (usize, usize) {
         (idx / self.width(), idx % self.width())
     }
 
-    pub fn get_neighbors(&self, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+    pub fn pos_to_idx(&self, (x, y): (usize, usize)) -> usize {
+        x * self.width() + y
+    }
+
+    /// Returns the neighbors of the cell at the given position, along with
+    /// the direction in which each of them lies.
+    ///
+    /// Cells on the borders have fewer neighbors, unless the table wraps
+    /// around that border.
+    pub fn get_neighbors(&self, (x, y): (usize, usize)) -> Vec<(Direction, (usize, usize))> {
         let mut neighbors = Vec::with_capacity(4);
 
         for d in Direction::all() {
             let (dx, dy) = d.add_pos((x as i32, y as i32));
-            if dx < 0 || dy < 0 || dx >= self.width() as i32 || dy >= self.height() as i32 {
+            let Some(dx) = wrap_coordinate(dx, self.height(), self.wrap.wraps_x()) else {
                 continue;
-            }
+            };
+            let Some(dy) = wrap_coordinate(dy, self.width(), self.wrap.wraps_y()) else {
+                continue;
+            };
 
-            neighbors.push((dx as usize, dy as usize));
+            neighbors.push((d, (dx, dy)));
         }
 
         neighbors
@@ -56,6 +117,18 @@ impl<T> Table<T> {
     }
 }
 
+/// Brings a coordinate back into `0..len`, or returns `None` if it's out of
+/// bounds and the axis doesn't wrap.
+pub(crate) fn wrap_coordinate(coordinate: i32, len: usize, wraps: bool) -> Option<usize> {
+    if wraps {
+        Some(coordinate.rem_euclid(len as i32) as usize)
+    } else if (0..len as i32).contains(&coordinate) {
+        Some(coordinate as usize)
+    } else {
+        None
+    }
+}
+
 impl<T> Index<usize> for Table<T> {
     type Output = T;
 
@@ -84,11 +157,33 @@ impl<T> IndexMut<(usize, usize)> for Table<T> {
     }
 }
 
+impl<T> Topology for Table<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn directions(&self) -> usize {
+        4
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        self.get_neighbors(self.idx_to_pos(idx))
+            .into_iter()
+            .map(|(d, pos)| (usize::from(d), self.pos_to_idx(pos)))
+            .collect()
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        usize::from(Direction::from(direction).opposite())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use itertools::Itertools;
 
-    use super::Table;
+    use super::{Table, Wrap};
+    use crate::direction::Direction;
 
     #[test]
     fn basic_properties() {
@@ -104,6 +199,9 @@ mod tests {
         assert_eq!(table.idx_to_pos(2), (0, 2));
         assert_eq!(table.idx_to_pos(3), (1, 0));
         assert_eq!(table.idx_to_pos(4), (1, 1));
+        for idx in 0..9 {
+            assert_eq!(table.pos_to_idx(table.idx_to_pos(idx)), idx);
+        }
     }
 
     #[test]
@@ -126,23 +224,74 @@ mod tests {
         // [2, 5, 8]
         let table = Table::new((0..9).collect_vec(), 3);
         let neighbors = table.get_neighbors((0, 0));
-        assert!(neighbors.contains(&(0, 1)));
-        assert!(neighbors.contains(&(1, 0)));
+        assert_eq!(neighbors.len(), 2);
+        assert!(neighbors.contains(&(Direction::Right, (0, 1))));
+        assert!(neighbors.contains(&(Direction::Down, (1, 0))));
 
         let neighbors = table.get_neighbors((1, 1));
-        dbg!(&neighbors);
-        assert!(neighbors.contains(&(0, 1)));
-        assert!(neighbors.contains(&(1, 0)));
-        assert!(neighbors.contains(&(2, 1)));
-        assert!(neighbors.contains(&(1, 2)));
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.contains(&(Direction::Up, (0, 1))));
+        assert!(neighbors.contains(&(Direction::Left, (1, 0))));
+        assert!(neighbors.contains(&(Direction::Down, (2, 1))));
+        assert!(neighbors.contains(&(Direction::Right, (1, 2))));
 
         let neighbors = table.get_neighbors((2, 2));
-        assert!(neighbors.contains(&(1, 2)));
-        assert!(neighbors.contains(&(2, 1)));
+        assert_eq!(neighbors.len(), 2);
+        assert!(neighbors.contains(&(Direction::Up, (1, 2))));
+        assert!(neighbors.contains(&(Direction::Left, (2, 1))));
 
         let neighbors = table.get_neighbors((2, 1));
-        assert!(neighbors.contains(&(1, 1)));
-        assert!(neighbors.contains(&(2, 0)));
-        assert!(neighbors.contains(&(2, 2)));
+        assert_eq!(neighbors.len(), 3);
+        assert!(neighbors.contains(&(Direction::Up, (1, 1))));
+        assert!(neighbors.contains(&(Direction::Left, (2, 0))));
+        assert!(neighbors.contains(&(Direction::Right, (2, 2))));
+    }
+
+    #[test]
+    fn get_neighbors_wrapping() {
+        // [0, 2, 4, 6]
+        // [1, 3, 5, 7]
+        let table = Table::new((0..8).collect_vec(), 2).with_wrap(Wrap::Both);
+        let neighbors = table.get_neighbors((0, 0));
+        assert_eq!(
+            neighbors,
+            vec![
+                (Direction::Up, (3, 0)),
+                (Direction::Right, (0, 1)),
+                (Direction::Down, (1, 0)),
+                (Direction::Left, (0, 1)),
+            ]
+        );
+
+        let table = Table::new((0..8).collect_vec(), 2).with_wrap(Wrap::Horizontal);
+        let neighbors = table.get_neighbors((3, 1));
+        assert_eq!(
+            neighbors,
+            vec![
+                (Direction::Up, (2, 1)),
+                (Direction::Down, (0, 1)),
+                (Direction::Left, (3, 0)),
+            ]
+        );
+
+        let table = Table::new((0..8).collect_vec(), 2).with_wrap(Wrap::Vertical);
+        let neighbors = table.get_neighbors((3, 1));
+        assert_eq!(
+            neighbors,
+            vec![
+                (Direction::Up, (2, 1)),
+                (Direction::Right, (3, 0)),
+                (Direction::Left, (3, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_wrap() {
+        assert_eq!("none".parse(), Ok(Wrap::None));
+        assert_eq!("horizontal".parse(), Ok(Wrap::Horizontal));
+        assert_eq!("vertical".parse(), Ok(Wrap::Vertical));
+        assert_eq!("both".parse(), Ok(Wrap::Both));
+        assert!("diagonal".parse::<Wrap>().is_err());
     }
 }
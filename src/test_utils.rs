@@ -1,27 +1,38 @@
-use image::{Rgb, RgbImage};
+use std::fmt::Debug;
 
-use crate::{
-    pattern::{Color, Pattern},
-    Image,
-};
+use image::Rgb;
 
-pub fn c(id: u8) -> Color {
-    Color::new(id, 0, 0)
+use crate::error::WfcError;
+use crate::{pattern::Pattern, texture::Texture};
+
+pub fn c(id: u8) -> Rgb<u8> {
+    Rgb([id, 0, 0])
 }
 
-pub fn p<'p>(id: usize, size: usize, texture: &'p Image, pos: (u32, u32)) -> Pattern<'p> {
+pub fn p(
+    id: usize,
+    size: usize,
+    texture: &Texture<Rgb<u8>>,
+    pos: (usize, usize),
+) -> Pattern<Rgb<u8>> {
     Pattern::new(id, size, texture, pos)
 }
 
-pub fn img(size: u32) -> RgbImage {
-    let mut texture = RgbImage::new(size, size);
-    let mut count = 0;
-    for y in 0..size {
-        for x in 0..size {
-            texture.put_pixel(y, x, Rgb([count, 0, 0]));
-            count += 1;
-        }
-    }
+pub fn img(size: usize) -> Texture<Rgb<u8>> {
+    Texture::from_fn(size, size, |x, y| c((x * size + y) as u8))
+}
 
-    texture
+/// Generates an output for a few seeds, checking that the same seed always
+/// produces the same output, and returns them so that each model can check
+/// what's specific to it.
+pub fn assert_deterministic<T: PartialEq + Debug>(
+    generate: impl Fn(u64) -> Result<T, WfcError>,
+) -> Vec<T> {
+    (0..4)
+        .map(|seed| {
+            let output = generate(seed).unwrap();
+            assert_eq!(output, generate(seed).unwrap(), "seed {seed}");
+            output
+        })
+        .collect()
 }
@@ -0,0 +1,240 @@
+use std::hash::Hash;
+use std::ops::{Deref, Index, IndexMut};
+use std::path::Path;
+
+use image::{ImageBuffer, Pixel};
+
+use crate::error::WfcError;
+
+/// The values an overlapping model can be made of, such as the pixels of an
+/// image, tile ids, characters or any small game-specific type.
+///
+/// The `par_` functions of the `parallel` feature share values between
+/// threads, so they also need them to be `Send` and `Sync`.
+pub trait CellValue: Copy + Eq + Hash {}
+
+impl<T: Copy + Eq + Hash> CellValue for T {}
+
+/// A rectangular grid of values, which the overlapping model takes its
+/// patterns from and generates.
+///
+/// Cells are stored row by row and indexed by `(x, y)`, with `y` growing
+/// downwards like in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Texture<T> {
+    cells: Vec<T>,
+    width: usize,
+    height: usize,
+}
+
+impl<T> Texture<T> {
+    /// Creates a `width`x`height` texture from its cells, row by row.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there aren't `width * height` cells.
+    pub fn new(cells: Vec<T>, width: usize, height: usize) -> Self {
+        assert_eq!(cells.len(), width * height);
+        Texture {
+            cells,
+            width,
+            height,
+        }
+    }
+
+    /// Creates a `width`x`height` texture whose cell at `(x, y)` is
+    /// `f(x, y)`.
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let cells = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Texture::new(cells, width, height)
+    }
+
+    /// Creates a texture from its rows, which must all be as long.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Self, WfcError> {
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(r) = rows.iter().position(|row| row.len() != width) {
+            return Err(WfcError::InvalidGrid {
+                message: format!(
+                    "row {r} has {} cells, but the first one has {width}",
+                    rows[r].len()
+                ),
+            });
+        }
+
+        let height = if width == 0 { 0 } else { rows.len() };
+        Ok(Texture::new(
+            rows.into_iter().flatten().collect(),
+            width,
+            height,
+        ))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// The cells, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.cells.chunks(self.width.max(1))
+    }
+
+    /// Applies `f` to every cell.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Texture<U> {
+        Texture::new(self.cells.iter().map(f).collect(), self.width, self.height)
+    }
+}
+
+impl Texture<char> {
+    /// Parses a grid with a row per line and a cell per character, such as
+    /// a map where each character is a kind of terrain.
+    ///
+    /// ```text
+    /// ####.....
+    /// #..#..~~.
+    /// ####..~~~
+    /// ```
+    ///
+    /// Empty lines are skipped.
+    pub fn from_text(text: &str) -> Result<Self, WfcError> {
+        let rows = text
+            .lines()
+            .map(|line| line.chars().collect())
+            .filter(|row: &Vec<char>| !row.is_empty())
+            .collect();
+
+        Texture::from_rows(rows)
+    }
+
+    /// Reads a text grid from a file. See [`Texture::from_text`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+        Texture::from_text(&text)
+    }
+
+    /// Writes the grid with a row per line, in the format read by
+    /// [`Texture::from_text`].
+    pub fn to_text(&self) -> String {
+        self.rows()
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), WfcError> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_text()).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })
+    }
+}
+
+impl<P: Pixel> Texture<P> {
+    /// Reads the pixels of an image.
+    pub fn from_image<C: Deref<Target = [P::Subpixel]>>(image: &ImageBuffer<P, C>) -> Self {
+        let (width, height) = image.dimensions();
+        Texture::new(
+            image.pixels().copied().collect(),
+            width as usize,
+            height as usize,
+        )
+    }
+
+    /// Draws the texture as an image, a pixel per cell.
+    pub fn to_image(&self) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            self[(x as usize, y as usize)]
+        })
+    }
+}
+
+impl<T> Index<(usize, usize)> for Texture<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        assert!(x < self.width && y < self.height);
+        &self.cells[y * self.width + x]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Texture<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut Self::Output {
+        assert!(x < self.width && y < self.height);
+        &mut self.cells[y * self.width + x]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::Texture;
+    use crate::error::WfcError;
+
+    #[test]
+    fn texture() {
+        let texture = Texture::from_fn(3, 2, |x, y| 10 * y + x);
+        assert_eq!(texture.dimensions(), (3, 2));
+        assert_eq!(texture[(2, 1)], 12);
+        assert_eq!(
+            texture.iter().copied().collect::<Vec<_>>(),
+            [0, 1, 2, 10, 11, 12]
+        );
+        assert_eq!(texture.rows().nth(1), Some(&[10, 11, 12][..]));
+        assert!(!texture.map(|&v| v % 2 == 0)[(1, 1)]);
+
+        assert_eq!(
+            Texture::from_rows(vec![vec![0, 1, 2], vec![10, 11, 12]]),
+            Ok(texture)
+        );
+        assert_eq!(
+            Texture::from_rows(vec![vec!['a', 'b'], vec!['c']]),
+            Err(WfcError::InvalidGrid {
+                message: "row 1 has 1 cells, but the first one has 2".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn text() {
+        let texture = Texture::from_text("#..\n\n#.~\r\n").unwrap();
+        assert_eq!(texture.dimensions(), (3, 2));
+        assert_eq!(texture[(2, 1)], '~');
+        assert_eq!(texture.to_text(), "#..\n#.~\n");
+
+        assert!(matches!(
+            Texture::from_text("#..\n#."),
+            Err(WfcError::InvalidGrid { .. })
+        ));
+    }
+
+    #[test]
+    fn image() {
+        let mut image = RgbImage::new(3, 2);
+        image.put_pixel(2, 0, Rgb([255, 0, 0]));
+        let texture = Texture::from_image(&image);
+        assert_eq!(texture.dimensions(), (3, 2));
+        assert_eq!(texture[(2, 0)], Rgb([255, 0, 0]));
+        assert_eq!(texture[(0, 1)], Rgb([0, 0, 0]));
+        assert_eq!(texture.to_image(), image);
+    }
+}
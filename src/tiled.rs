@@ -0,0 +1,856 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::{imageops, ImageBuffer, Rgb};
+use itertools::iproduct;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::compatibility::Compatibility;
+use crate::direction::Direction;
+use crate::error::WfcError;
+use crate::pattern;
+use crate::table::Wrap;
+use crate::texture::Texture;
+use crate::tiles::TileSolver;
+use crate::Image;
+
+/// How the orientations of a tile map onto each other under rotations and
+/// reflections, following the classes used by Gumin's tilesets.
+///
+/// The name of each class is a letter with the same symmetries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymmetryClass {
+    /// Looks the same in every orientation.
+    X,
+    /// Has four orientations, and reflecting it swaps pairs of them.
+    L,
+    /// Has four orientations, and is symmetric along one axis.
+    T,
+    /// Has two orientations, and is symmetric along both axes.
+    I,
+    /// Has two orientations, and is symmetric along a diagonal.
+    Backslash,
+    /// Has no symmetries, so all eight orientations are different.
+    F,
+}
+
+impl SymmetryClass {
+    /// The amount of different orientations of a tile.
+    pub fn cardinality(self) -> usize {
+        match self {
+            SymmetryClass::X => 1,
+            SymmetryClass::I | SymmetryClass::Backslash => 2,
+            SymmetryClass::L | SymmetryClass::T => 4,
+            SymmetryClass::F => 8,
+        }
+    }
+
+    /// The orientation of a tile in orientation `i` after rotating it by 90
+    /// degrees.
+    fn rotate(self, i: usize) -> usize {
+        match self {
+            SymmetryClass::X => i,
+            SymmetryClass::I | SymmetryClass::Backslash => 1 - i,
+            SymmetryClass::L | SymmetryClass::T => (i + 1) % 4,
+            SymmetryClass::F if i < 4 => (i + 1) % 4,
+            SymmetryClass::F => 4 + (i + 3) % 4,
+        }
+    }
+
+    /// The orientation of a tile in orientation `i` after reflecting it.
+    fn reflect(self, i: usize) -> usize {
+        match self {
+            SymmetryClass::X | SymmetryClass::I => i,
+            SymmetryClass::Backslash => 1 - i,
+            SymmetryClass::L if i.is_multiple_of(2) => i + 1,
+            SymmetryClass::L => i - 1,
+            SymmetryClass::T if i.is_multiple_of(2) => i,
+            SymmetryClass::T => 4 - i,
+            SymmetryClass::F if i < 4 => i + 4,
+            SymmetryClass::F => i - 4,
+        }
+    }
+}
+
+impl FromStr for SymmetryClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "X" => Ok(SymmetryClass::X),
+            "L" => Ok(SymmetryClass::L),
+            "T" => Ok(SymmetryClass::T),
+            "I" => Ok(SymmetryClass::I),
+            "\\" => Ok(SymmetryClass::Backslash),
+            "F" => Ok(SymmetryClass::F),
+            _ => Err(format!("expected one of X, L, T, I, \\ or F, got {s}")),
+        }
+    }
+}
+
+/// The simple tiled model.
+///
+/// It generates images by laying out square tiles on a grid, so that every
+/// pair of adjacent tiles is allowed by the neighbor rules of the tileset.
+pub struct TiledModel {
+    /// The image of every tile variant, indexed by the variant id.
+    tiles: Vec<Image>,
+    /// The width and height of the tiles, in pixels.
+    tile_size: u32,
+    /// The tile variants that can be put next to each tile variant, and how
+    /// to fill a grid with them.
+    solver: TileSolver,
+}
+
+impl TiledModel {
+    /// Loads a tileset in the format of Gumin's samples.
+    ///
+    /// The tile images are read as PNG files from the directory of the XML
+    /// file. See [`TiledModel::from_xml`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        let path = path.as_ref();
+        let xml = std::fs::read_to_string(path).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        TiledModel::from_xml(&xml, |name| open(dir.join(format!("{name}.png"))))
+    }
+
+    /// Loads every PNG file in a directory as a tile, in the order of their
+    /// file names. See [`TiledModel::from_edges`].
+    pub fn load_dir(path: impl AsRef<Path>, tolerance: u8) -> Result<Self, WfcError> {
+        let path = path.as_ref();
+        let io = |err: std::io::Error| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        };
+
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(io)? {
+            let entry = entry.map_err(io)?.path();
+            if entry.extension().is_some_and(|ext| ext == "png") {
+                paths.push(entry);
+            }
+        }
+        paths.sort();
+
+        let tiles = paths.into_iter().map(open).collect::<Result<_, _>>()?;
+        TiledModel::from_edges(tiles, tolerance)
+    }
+
+    /// Builds the model from tiles that can be put next to each other when
+    /// the pixels along their touching edges match.
+    ///
+    /// Pixels match when none of their channels differ by more than
+    /// `tolerance`. Every tile is used as is, with a weight of `1`.
+    pub fn from_edges(tiles: Vec<Image>, tolerance: u8) -> Result<Self, WfcError> {
+        let tile_size = tile_size(&tiles)?;
+        let edges = tiles
+            .iter()
+            .map(|tile| Direction::all().map(|d| edge(tile, d)))
+            .collect::<Vec<_>>();
+
+        let mut compatibility = Compatibility::new(tiles.len());
+        for (t1, t2) in iproduct!(0..tiles.len(), 0..tiles.len()) {
+            for d in Direction::all() {
+                let side1 = &edges[t1][usize::from(d)];
+                let side2 = &edges[t2][usize::from(d.opposite())];
+                if edges_match(side1, side2, tolerance) {
+                    compatibility.insert(t1, t2, d);
+                }
+            }
+        }
+
+        Ok(TiledModel {
+            solver: TileSolver::new(compatibility, vec![1.0; tiles.len()]),
+            tiles,
+            tile_size,
+        })
+    }
+
+    /// Builds the model from the XML description of a tileset, calling
+    /// `load_tile` to get the image with the given name.
+    ///
+    /// The tileset lists its tiles under `<tiles>`, each with a `name`, a
+    /// `symmetry` class and a `weight`, and which tiles may be put to the
+    /// left of which under `<neighbors>`. Tiles are referred to by their
+    /// name, optionally followed by one of their orientations:
+    ///
+    /// ```xml
+    /// <set>
+    ///   <tiles>
+    ///     <tile name="grass" symmetry="X" weight="4"/>
+    ///     <tile name="road" symmetry="I"/>
+    ///   </tiles>
+    ///   <neighbors>
+    ///     <neighbor left="grass" right="road 1"/>
+    ///   </neighbors>
+    /// </set>
+    /// ```
+    ///
+    /// The rotations and reflections of every tile are added as variants of
+    /// their own. If the set is marked as `unique`, the image of every
+    /// variant is loaded as `"<name> <orientation>"`; otherwise the image
+    /// of the tile is loaded as `"<name>"` and transformed.
+    pub fn from_xml(
+        xml: &str,
+        mut load_tile: impl FnMut(&str) -> Result<Image, WfcError>,
+    ) -> Result<Self, WfcError> {
+        let doc = roxmltree::Document::parse(xml).map_err(|err| invalid(err.to_string()))?;
+        let set = doc.root_element();
+        let unique = set
+            .attribute("unique")
+            .is_some_and(|unique| unique.eq_ignore_ascii_case("true"));
+        let child = |name: &str| {
+            set.children()
+                .find(|node| node.has_tag_name(name))
+                .ok_or_else(|| invalid(format!("missing <{name}>")))
+        };
+
+        // The variant each variant turns into under every rotation and
+        // reflection, and the first variant of each tile.
+        let mut actions: Vec<[usize; 8]> = Vec::new();
+        let mut first: HashMap<&str, usize> = HashMap::default();
+        let mut tiles = Vec::new();
+        let mut weights = Vec::new();
+        for tile in child("tiles")?
+            .children()
+            .filter(|n| n.has_tag_name("tile"))
+        {
+            let name = tile
+                .attribute("name")
+                .ok_or_else(|| invalid("a tile has no name".to_string()))?;
+            let symmetry = tile.attribute("symmetry").unwrap_or("X");
+            let symmetry =
+                symmetry
+                    .parse::<SymmetryClass>()
+                    .map_err(|_| WfcError::InvalidSymmetryClass {
+                        tile: name.to_string(),
+                        symmetry: symmetry.to_string(),
+                    })?;
+            let weight = match tile.attribute("weight") {
+                Some(weight) => weight
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|w| *w >= 0.0 && w.is_finite()),
+                None => Some(1.0),
+            }
+            .ok_or_else(|| invalid(format!("the weight of tile {name:?} isn't valid")))?;
+
+            let offset = actions.len();
+            if first.insert(name, offset).is_some() {
+                return Err(invalid(format!("there are two tiles named {name:?}")));
+            }
+
+            for t in 0..symmetry.cardinality() {
+                let a = |i| symmetry.rotate(i);
+                let b = |i| symmetry.reflect(i);
+                let action = [
+                    t,
+                    a(t),
+                    a(a(t)),
+                    a(a(a(t))),
+                    b(t),
+                    b(a(t)),
+                    b(a(a(t))),
+                    b(a(a(a(t)))),
+                ];
+                actions.push(action.map(|i| offset + i));
+                weights.push(weight);
+
+                let image = if unique {
+                    load_tile(&format!("{name} {t}"))?
+                } else if t == 0 {
+                    load_tile(name)?
+                } else if t <= 3 {
+                    rotate(&tiles[offset + t - 1])
+                } else {
+                    reflect(&tiles[offset + t - 4])
+                };
+                tiles.push(image);
+            }
+        }
+
+        let tile_size = tile_size(&tiles)?;
+
+        let variant = |name: &str| -> Result<usize, WfcError> {
+            let mut parts = name.split_whitespace();
+            let tile = parts.next().unwrap_or_default();
+            let &offset = first.get(tile).ok_or_else(|| WfcError::UnknownTile {
+                name: tile.to_string(),
+            })?;
+            let orientation = match parts.next() {
+                Some(orientation) => orientation.parse::<usize>().ok().filter(|&o| o < 8),
+                None => Some(0),
+            }
+            .ok_or_else(|| invalid(format!("{name:?} isn't a valid orientation")))?;
+
+            Ok(actions[offset][orientation])
+        };
+
+        // Rows of the solver's table are columns of the output, so `Up`
+        // points to the tile on the left and `Right` to the tile below.
+        let mut allowed = Vec::new();
+        let mut allow = |t1: usize, t2: usize, d: Direction| {
+            allowed.push((t1, usize::from(d), t2));
+            allowed.push((t2, usize::from(d.opposite()), t1));
+        };
+        let neighbors = child("neighbors")?;
+        for neighbor in neighbors.children().filter(|n| n.has_tag_name("neighbor")) {
+            let attribute = |name| {
+                neighbor
+                    .attribute(name)
+                    .ok_or_else(|| invalid(format!("a neighbor rule has no {name}")))
+            };
+            let l = variant(attribute("left")?)?;
+            let r = variant(attribute("right")?)?;
+            // Rotating a pair that's side by side puts one above the other.
+            let d = actions[l][1];
+            let u = actions[r][1];
+
+            // `l` can be to the left of `r`, and so can their reflections
+            // and their upside-down versions.
+            allow(r, l, Direction::Up);
+            allow(actions[r][6], actions[l][6], Direction::Up);
+            allow(actions[l][4], actions[r][4], Direction::Up);
+            allow(actions[l][2], actions[r][2], Direction::Up);
+
+            // `d` can be below `u`.
+            allow(u, d, Direction::Right);
+            allow(actions[d][6], actions[u][6], Direction::Right);
+            allow(actions[u][4], actions[d][4], Direction::Right);
+            allow(actions[d][2], actions[u][2], Direction::Right);
+        }
+
+        // Sparse tables need the compatible tiles in increasing order.
+        allowed.sort_unstable();
+        allowed.dedup();
+        let mut compatibility = Compatibility::new(tiles.len());
+        for (t1, d, t2) in allowed {
+            compatibility.insert(t1, t2, Direction::from(d));
+        }
+
+        Ok(TiledModel {
+            tiles,
+            tile_size,
+            solver: TileSolver::new(compatibility, weights),
+        })
+    }
+
+    /// Learns the tiles from an example image made of `tile_size`x`tile_size`
+    /// tiles.
+    ///
+    /// Equal tiles are merged into one, weighted by how many times it
+    /// appears. Two tiles can only be put next to each other the way they
+    /// are somewhere in the example. If `periodic` is true, the example is
+    /// treated as if it wrapped around its borders.
+    pub fn from_example(example: &Image, tile_size: u32, periodic: bool) -> Result<Self, WfcError> {
+        if tile_size == 0 {
+            return Err(WfcError::ZeroPatternSize);
+        }
+
+        let (width, height) = example.dimensions();
+        if width % tile_size != 0 || height % tile_size != 0 {
+            return Err(invalid(format!(
+                "a {width}x{height} example can't be split into {tile_size}x{tile_size} tiles"
+            )));
+        }
+
+        let (tiles, grid) = pattern::get_tiles(&Texture::from_image(example), tile_size as usize);
+        let grid = grid.with_wrap(if periodic { Wrap::Both } else { Wrap::None });
+
+        // Sparse tables need the compatible tiles in increasing order.
+        let mut observed = Vec::new();
+        for (idx, &t1) in grid.iter().enumerate() {
+            for (d, neighbor) in grid.get_neighbors(grid.idx_to_pos(idx)) {
+                observed.push((t1, usize::from(d), grid[neighbor]));
+            }
+        }
+        observed.sort_unstable();
+        observed.dedup();
+
+        let mut compatibility = Compatibility::new(tiles.len());
+        for (t1, d, t2) in observed {
+            compatibility.insert(t1, t2, Direction::from(d));
+        }
+
+        let weights = tiles.iter().map(|tile| tile.frequency as f64).collect();
+        Ok(TiledModel {
+            tiles: tiles
+                .iter()
+                .map(|tile| {
+                    ImageBuffer::from_fn(tile_size, tile_size, |x, y| {
+                        tile.cells[(x * tile_size + y) as usize]
+                    })
+                })
+                .collect(),
+            tile_size,
+            solver: TileSolver::new(compatibility, weights),
+        })
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.solver.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Sets the axes along which the output wraps around.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.solver.wrap = wrap;
+        self
+    }
+
+    /// Generates an image `width` tiles wide and `height` tiles tall.
+    ///
+    /// The same seed always produces the same output for the same tileset.
+    ///
+    /// Fails if the image would be more than `u32::MAX` pixels wide or
+    /// tall, besides the ways [`Wfc::generate`](crate::Wfc::generate)
+    /// fails.
+    pub fn generate(&self, width: usize, height: usize, seed: u64) -> Result<Image, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(width, height, &mut rng)
+    }
+
+    /// Like [`TiledModel::generate`], but draws every random choice from
+    /// `rng`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> Result<Image, WfcError> {
+        let size = self.tile_size;
+        let pixels = |tiles: usize| u32::try_from(tiles).ok()?.checked_mul(size);
+        let (Some(pixel_width), Some(pixel_height)) = (pixels(width), pixels(height)) else {
+            return Err(WfcError::OutputTooLarge { width, height });
+        };
+
+        let rows = self.solver.generate_rows(width, height, rng)?;
+
+        let mut buffer = ImageBuffer::new(pixel_width, pixel_height);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &id) in row.iter().enumerate() {
+                let (x, y) = (x as i64 * size as i64, y as i64 * size as i64);
+                imageops::replace(&mut buffer, &self.tiles[id], x, y);
+            }
+        }
+
+        Ok(buffer)
+    }
+}
+
+fn invalid(message: String) -> WfcError {
+    WfcError::InvalidTileset { message }
+}
+
+fn open(path: PathBuf) -> Result<Image, WfcError> {
+    match image::open(&path) {
+        Ok(image) => Ok(image.to_rgb8()),
+        Err(err) => Err(WfcError::Io {
+            path,
+            message: err.to_string(),
+        }),
+    }
+}
+
+/// Returns the size of the tiles, failing if they aren't squares of the
+/// same size.
+fn tile_size(tiles: &[Image]) -> Result<u32, WfcError> {
+    let size = tiles.first().map_or(0, |tile| tile.width());
+    if tiles.iter().any(|tile| tile.dimensions() != (size, size)) {
+        return Err(invalid(
+            "the tiles aren't squares of the same size".to_string(),
+        ));
+    }
+
+    Ok(size)
+}
+
+/// Returns the pixels along the edge of a tile that faces the given
+/// direction.
+///
+/// Like [`crate::pattern::Pattern::get_side`], directions follow the
+/// solver's table, whose rows are columns of the output, so `Up` is the
+/// left column and `Left` is the top row.
+fn edge(tile: &Image, direction: Direction) -> Vec<Rgb<u8>> {
+    let last = tile.width().saturating_sub(1);
+    let pixels = 0..tile.width();
+    match direction {
+        Direction::Up => pixels.map(|y| *tile.get_pixel(0, y)).collect(),
+        Direction::Down => pixels.map(|y| *tile.get_pixel(last, y)).collect(),
+        Direction::Left => pixels.map(|x| *tile.get_pixel(x, 0)).collect(),
+        Direction::Right => pixels.map(|x| *tile.get_pixel(x, last)).collect(),
+    }
+}
+
+/// Checks whether two edges match, pixel by pixel, allowing each channel
+/// to differ by up to `tolerance`.
+fn edges_match(a: &[Rgb<u8>], b: &[Rgb<u8>], tolerance: u8) -> bool {
+    a.iter().zip(b).all(|(p, q)| {
+        p.0.iter()
+            .zip(q.0)
+            .all(|(&c1, c2)| c1.abs_diff(c2) <= tolerance)
+    })
+}
+
+/// Rotates a square tile by 90 degrees, counterclockwise.
+fn rotate(tile: &Image) -> Image {
+    let size = tile.width();
+    ImageBuffer::from_fn(size, size, |x, y| *tile.get_pixel(size - 1 - y, x))
+}
+
+/// Mirrors a square tile horizontally.
+fn reflect(tile: &Image) -> Image {
+    let size = tile.width();
+    ImageBuffer::from_fn(size, size, |x, y| *tile.get_pixel(size - 1 - x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgb};
+    use itertools::Itertools;
+
+    use super::{reflect, rotate, SymmetryClass, TiledModel};
+    use crate::compatibility::DENSE_LIMIT;
+    use crate::direction::Direction;
+    use crate::error::WfcError;
+    use crate::test_utils::assert_deterministic;
+    use crate::Image;
+
+    /// A tile of the given size with a single pixel of `color` in its top
+    /// left corner, so that its orientation can be told apart.
+    fn tile(size: u32, color: u8) -> Image {
+        ImageBuffer::from_fn(size, size, |x, y| {
+            if (x, y) == (0, 0) {
+                Rgb([color, 0, 0])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        })
+    }
+
+    fn load(xml: &str) -> Result<TiledModel, WfcError> {
+        TiledModel::from_xml(xml, |name| match name {
+            "small" => Ok(tile(1, 1)),
+            _ => Ok(tile(2, name.len() as u8)),
+        })
+    }
+
+    #[test]
+    fn symmetry_classes() {
+        let classes = [
+            SymmetryClass::X,
+            SymmetryClass::L,
+            SymmetryClass::T,
+            SymmetryClass::I,
+            SymmetryClass::Backslash,
+            SymmetryClass::F,
+        ];
+        for class in classes {
+            for i in 0..class.cardinality() {
+                let rotated = (0..4).fold(i, |i, _| class.rotate(i));
+                assert_eq!(rotated, i, "{class:?}");
+                assert_eq!(class.reflect(class.reflect(i)), i, "{class:?}");
+            }
+        }
+
+        assert_eq!("\\".parse(), Ok(SymmetryClass::Backslash));
+        assert!("Y".parse::<SymmetryClass>().is_err());
+
+        let model = load(
+            r#"<set>
+                <tiles>
+                    <tile name="x" symmetry="X"/>
+                    <tile name="l" symmetry="L"/>
+                    <tile name="t" symmetry="T"/>
+                    <tile name="i" symmetry="I"/>
+                    <tile name="d" symmetry="\"/>
+                    <tile name="f" symmetry="F"/>
+                </tiles>
+                <neighbors/>
+            </set>"#,
+        )
+        .unwrap();
+        assert_eq!(model.tiles.len(), 21);
+        assert_eq!(model.tile_size, 2);
+
+        // The variants of `L` are its rotations.
+        let l = &model.tiles[1..5];
+        for (a, b) in l.iter().circular_tuple_windows() {
+            assert_eq!(&rotate(a), b);
+        }
+
+        // The last four variants of `F` are reflections of the first four.
+        let f = &model.tiles[13..21];
+        for i in 0..4 {
+            assert_eq!(reflect(&f[i]), f[i + 4]);
+        }
+    }
+
+    #[test]
+    fn neighbors() {
+        // An `a` can go to the left of a `b`, and a `b` can go to the left
+        // of a `c`.
+        let model = load(
+            r#"<set>
+                <tiles>
+                    <tile name="a" symmetry="X"/>
+                    <tile name="b" symmetry="I"/>
+                    <tile name="c" symmetry="X"/>
+                </tiles>
+                <neighbors>
+                    <neighbor left="a" right="b"/>
+                    <neighbor left="b 1" right="c"/>
+                </neighbors>
+            </set>"#,
+        )
+        .unwrap();
+        let (a, b0, b1, c) = (0, 1, 2, 3);
+        let compatibility = &model.solver.compatibility;
+
+        // `Up` points to the left, and `Right` points down.
+        assert!(compatibility.contains(b0, a, Direction::Up));
+        assert!(compatibility.contains(a, b0, Direction::Down));
+        assert!(!compatibility.contains(b1, a, Direction::Up));
+
+        // `b` is symmetric, so `a` can also go to its right.
+        assert!(compatibility.contains(a, b0, Direction::Up));
+
+        // Rotating the pair puts the `a` below the rotated `b`.
+        assert!(compatibility.contains(b1, a, Direction::Right));
+        assert!(compatibility.contains(a, b1, Direction::Left));
+        assert!(!compatibility.contains(b0, a, Direction::Right));
+
+        assert!(compatibility.contains(c, b1, Direction::Up));
+        assert!(compatibility.contains(b0, c, Direction::Right));
+        assert!(!compatibility.contains(a, c, Direction::Up));
+
+        // Large tilesets are stored sparsely, with the same rules.
+        let tiles = (0..=DENSE_LIMIT / 8)
+            .map(|i| format!(r#"<tile name="f{i}" symmetry="F"/>"#))
+            .collect::<String>();
+        let xml = format!(
+            r#"<set><tiles>{tiles}</tiles><neighbors>
+                <neighbor left="f0" right="f1 2"/>
+                <neighbor left="f1" right="f0"/>
+            </neighbors></set>"#
+        );
+        let model = load(&xml).unwrap();
+        let compatibility = &model.solver.compatibility;
+        assert!(compatibility.is_sparse());
+        assert!(compatibility.contains(10, 0, Direction::Up));
+        assert!(compatibility.contains(0, 8, Direction::Up));
+        assert!(compatibility.contains(0, 10, Direction::Down));
+        assert!(!compatibility.contains(0, 9, Direction::Down));
+    }
+
+    #[test]
+    fn generate() {
+        // Black and white tiles that can only be put next to each other.
+        let xml = r#"<set>
+            <tiles>
+                <tile name="black"/>
+                <tile name="white" weight="2"/>
+            </tiles>
+            <neighbors>
+                <neighbor left="black" right="white"/>
+            </neighbors>
+        </set>"#;
+        let model = TiledModel::from_xml(xml, |name| {
+            let color = if name == "white" { 255 } else { 0 };
+            Ok(ImageBuffer::from_pixel(2, 2, Rgb([color; 3])))
+        })
+        .unwrap();
+
+        let output = model.generate(3, 4, 0).unwrap();
+        assert_eq!(output.dimensions(), (6, 8));
+        let corner = output.get_pixel(0, 0)[0];
+        for (x, y) in (0..6).cartesian_product(0..8) {
+            let flipped = (x / 2 + y / 2) % 2 == 1;
+            let expected = if flipped { 255 - corner } else { corner };
+            assert_eq!(output.get_pixel(x, y)[0], expected, "{x}, {y}");
+        }
+
+        assert_deterministic(|seed| model.generate(5, 5, seed));
+    }
+
+    #[test]
+    fn from_edges() {
+        // Black tiles with a line running through the middle of them, along
+        // either axis of the image.
+        let black = Rgb([0, 0, 0]);
+        let line = |horizontal: bool, color: u8| -> Image {
+            ImageBuffer::from_fn(3, 3, |x, y| {
+                let on_line = if horizontal { y == 1 } else { x == 1 };
+                if on_line {
+                    Rgb([color; 3])
+                } else {
+                    black
+                }
+            })
+        };
+        let tiles = vec![
+            ImageBuffer::from_pixel(3, 3, black),
+            line(true, 255),
+            line(false, 255),
+            line(true, 250),
+        ];
+        let (empty, horizontal, vertical, dim) = (0, 1, 2, 3);
+
+        let model = TiledModel::from_edges(tiles.clone(), 0).unwrap();
+        let compatibility = &model.solver.compatibility;
+        // `Up` points to the tile on the left, and `Right` to the tile below.
+        assert!(compatibility.contains(horizontal, horizontal, Direction::Up));
+        assert!(compatibility.contains(horizontal, empty, Direction::Right));
+        assert!(!compatibility.contains(horizontal, empty, Direction::Up));
+        assert!(compatibility.contains(vertical, vertical, Direction::Right));
+        assert!(compatibility.contains(vertical, empty, Direction::Down));
+        assert!(!compatibility.contains(vertical, horizontal, Direction::Up));
+        assert!(!compatibility.contains(dim, horizontal, Direction::Up));
+
+        let model = TiledModel::from_edges(tiles, 5).unwrap();
+        assert!(model
+            .solver
+            .compatibility
+            .contains(dim, horizontal, Direction::Up));
+        assert!(model
+            .solver
+            .compatibility
+            .contains(horizontal, dim, Direction::Down));
+
+        // The pixels on both sides of every seam match.
+        let output = model.generate(6, 5, 0).unwrap();
+        for x in (3..18).step_by(3) {
+            for y in 0..15 {
+                let (left, right) = (output.get_pixel(x - 1, y), output.get_pixel(x, y));
+                assert!(super::edges_match(&[*left], &[*right], 5), "{x}, {y}");
+            }
+        }
+        for y in (3..15).step_by(3) {
+            for x in 0..18 {
+                let (above, below) = (output.get_pixel(x, y - 1), output.get_pixel(x, y));
+                assert!(super::edges_match(&[*above], &[*below], 5), "{x}, {y}");
+            }
+        }
+
+        assert!(matches!(
+            TiledModel::from_edges(vec![tile(2, 0), tile(3, 0)], 0),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+    }
+
+    #[test]
+    fn from_example() {
+        // A road that runs along the image, between grass and water:
+        // [g, g, g]
+        // [r, r, r]
+        // [w, w, w]
+        let colors = [[0, 255, 0], [128, 128, 128], [0, 0, 255]];
+        let example: Image = ImageBuffer::from_fn(6, 6, |_, y| Rgb(colors[y as usize / 2]));
+        let model = TiledModel::from_example(&example, 2, false).unwrap();
+        let (grass, road, water) = (0, 1, 2);
+        assert_eq!(model.tiles.len(), 3);
+        assert_eq!(model.solver.weights, vec![3.0; 3]);
+        assert_eq!(
+            model.tiles[road],
+            ImageBuffer::from_pixel(2, 2, Rgb(colors[1]))
+        );
+
+        // Only the observed neighbors are allowed.
+        let compatibility = &model.solver.compatibility;
+        assert!(compatibility.contains(grass, road, Direction::Right));
+        assert!(compatibility.contains(road, grass, Direction::Left));
+        assert!(compatibility.contains(road, road, Direction::Up));
+        assert!(!compatibility.contains(grass, water, Direction::Right));
+        assert!(!compatibility.contains(road, grass, Direction::Right));
+
+        // Grass has never been seen below anything, nor water above, so the
+        // only way to fill three rows is the example's.
+        let output = model.generate(4, 3, 0).unwrap();
+        assert_eq!(output.dimensions(), (8, 6));
+        for (x, y) in (0..8).cartesian_product(0..6) {
+            assert_eq!(output.get_pixel(x, y).0, colors[y as usize / 2]);
+        }
+        assert!(model.generate(4, 4, 0).is_err());
+
+        // Wrapping around the example lets water be above grass, so the
+        // rows cycle.
+        let model = TiledModel::from_example(&example, 2, true).unwrap();
+        assert!(model
+            .solver
+            .compatibility
+            .contains(water, grass, Direction::Right));
+        let output = model.generate(4, 9, 0).unwrap();
+        let first = output.get_pixel(0, 0).0;
+        let offset = colors.iter().position(|&c| c == first).unwrap();
+        for (x, y) in (0..8).cartesian_product(0..18) {
+            let expected = colors[(offset + y as usize / 2) % 3];
+            assert_eq!(output.get_pixel(x, y).0, expected);
+        }
+
+        assert_eq!(
+            TiledModel::from_example(&example, 0, false).err(),
+            Some(WfcError::ZeroPatternSize)
+        );
+        assert!(matches!(
+            TiledModel::from_example(&example, 4, false),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let tileset = |tiles: &str, neighbors: &str| {
+            format!("<set><tiles>{tiles}</tiles><neighbors>{neighbors}</neighbors></set>")
+        };
+
+        let xml = tileset(r#"<tile name="a"/>"#, r#"<neighbor left="a" right="b"/>"#);
+        assert_eq!(
+            load(&xml).err(),
+            Some(WfcError::UnknownTile {
+                name: "b".to_string()
+            })
+        );
+
+        let xml = tileset(r#"<tile name="a" symmetry="Y"/>"#, "");
+        assert_eq!(
+            load(&xml).err(),
+            Some(WfcError::InvalidSymmetryClass {
+                tile: "a".to_string(),
+                symmetry: "Y".to_string()
+            })
+        );
+
+        let invalid = [
+            "<set><tiles/></set>".to_string(),
+            "<set>".to_string(),
+            tileset(r#"<tile name="a"/><tile name="a"/>"#, ""),
+            tileset(r#"<tile name="a" weight="-1"/>"#, ""),
+            tileset(r#"<tile name="a"/><tile name="small"/>"#, ""),
+            tileset(r#"<tile name="a"/>"#, r#"<neighbor left="a 8" right="a"/>"#),
+        ];
+        for xml in invalid {
+            assert!(
+                matches!(load(&xml), Err(WfcError::InvalidTileset { .. })),
+                "{xml}"
+            );
+        }
+
+        let model = load(&tileset(r#"<tile name="a" weight="0"/>"#, "")).unwrap();
+        assert_eq!(model.generate(2, 2, 0), Err(WfcError::NoPatterns));
+        assert_eq!(
+            model.generate(1 << 31, 1, 0),
+            Err(WfcError::OutputTooLarge {
+                width: 1 << 31,
+                height: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use rand::Rng;
+use rustc_hash::FxHashSet as HashSet;
+
+use crate::compatibility::Compatibility;
+use crate::error::WfcError;
+use crate::table::Wrap;
+use crate::wfc::{solve, DEFAULT_BACKTRACK_LIMIT};
+
+/// Checks that no two tiles share a name and that every weight is valid,
+/// returning the weights.
+///
+/// Tiles are given by their name and weight.
+pub(crate) fn check_tiles<'t>(
+    tiles: impl IntoIterator<Item = (&'t str, f64)>,
+) -> Result<Vec<f64>, WfcError> {
+    let mut names = HashSet::default();
+    let mut weights = Vec::new();
+    for (name, weight) in tiles {
+        if !names.insert(name) {
+            return Err(WfcError::InvalidTileset {
+                message: format!("there are two tiles named {name:?}"),
+            });
+        }
+
+        if !(weight >= 0.0 && weight.is_finite()) {
+            return Err(WfcError::InvalidTileset {
+                message: format!("the weight of tile {name:?} isn't valid"),
+            });
+        }
+
+        weights.push(weight);
+    }
+
+    Ok(weights)
+}
+
+/// Fills grids with the tiles of a model whose rules have already been
+/// turned into a constraints table, such as the tiled, socket and Wang
+/// models.
+pub(crate) struct TileSolver {
+    /// The tiles that can be put next to each tile.
+    pub(crate) compatibility: Compatibility,
+    /// The weight of every tile.
+    pub(crate) weights: Vec<f64>,
+    /// The maximum amount of times the solver may backtrack in a single
+    /// generation.
+    pub(crate) backtrack_limit: usize,
+    /// The axes along which the output wraps around.
+    pub(crate) wrap: Wrap,
+}
+
+impl TileSolver {
+    pub(crate) fn new(compatibility: Compatibility, weights: Vec<f64>) -> Self {
+        TileSolver {
+            compatibility,
+            weights,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            wrap: Wrap::None,
+        }
+    }
+
+    /// Generates a grid `width` tiles wide and `height` tiles tall, as rows
+    /// of tile ids.
+    pub(crate) fn generate_rows<R: Rng + ?Sized>(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Vec<usize>>, WfcError> {
+        let ids = solve(
+            &self.compatibility,
+            self.weights.clone(),
+            (width, height),
+            self.wrap,
+            self.backtrack_limit,
+            rng,
+        )?;
+
+        let mut rows = vec![vec![0; width]; height];
+        for (idx, &id) in ids.iter().enumerate() {
+            let (x, y) = ids.idx_to_pos(idx);
+            rows[y][x] = id;
+        }
+
+        Ok(rows)
+    }
+}
@@ -0,0 +1,463 @@
+use rustc_hash::FxHashSet as HashSet;
+
+use crate::direction::{transpose, Direction};
+use crate::error::WfcError;
+use crate::table::{wrap_coordinate, Wrap};
+
+/// The cells of an output and the labeled edges between them.
+///
+/// Edge labels are the directions of the [`Compatibility`] table the output
+/// is solved with, so a pattern `p2` can be put on the other end of an edge
+/// labeled `d` leaving a cell holding `p1` when `p2` is compatible with
+/// `p1` in direction `d`.
+///
+/// Edges must come in pairs: if `b` is a neighbor of `a` with label `d`,
+/// then `a` must be a neighbor of `b` with label `opposite(d)`.
+///
+/// [`Compatibility`]: crate::Compatibility
+pub trait Topology {
+    /// The amount of cells.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The amount of edge labels.
+    fn directions(&self) -> usize;
+
+    /// The label of each edge leaving the cell at `idx`, and the index of
+    /// the cell it leads to.
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)>;
+
+    /// The label of the edge leading back along an edge labeled `direction`.
+    fn opposite(&self, direction: usize) -> usize;
+}
+
+impl<T: Topology + ?Sized> Topology for &T {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn directions(&self) -> usize {
+        (**self).directions()
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        (**self).neighbors(idx)
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        (**self).opposite(direction)
+    }
+}
+
+/// A rectangular grid, whose edges are labeled with a [`Direction`] the way
+/// the crate's constraints tables use them.
+///
+/// Rows of those tables are columns of the output, so `Up` leads to the
+/// previous column and `Left` to the previous row. A table built from
+/// directions as seen in the output needs them swapped accordingly.
+///
+/// Cells are stored row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    wrap: Wrap,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            height,
+            wrap: Wrap::None,
+        }
+    }
+
+    /// Makes the grid wrap around along the given axes, so that cells on
+    /// opposite borders are neighbors.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn idx_to_pos(&self, idx: usize) -> (usize, usize) {
+        (idx % self.width, idx / self.width)
+    }
+
+    pub fn pos_to_idx(&self, (x, y): (usize, usize)) -> usize {
+        y * self.width + x
+    }
+}
+
+impl Topology for Grid {
+    fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn directions(&self) -> usize {
+        4
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        let (x, y) = self.idx_to_pos(idx);
+        let mut neighbors = Vec::with_capacity(4);
+
+        for d in Direction::all() {
+            let (dx, dy) = match d {
+                Direction::Up => (0, -1),
+                Direction::Right => (1, 0),
+                Direction::Down => (0, 1),
+                Direction::Left => (-1, 0),
+            };
+            let Some(nx) = wrap_coordinate(x as i32 + dx, self.width, self.wrap.wraps_x()) else {
+                continue;
+            };
+            let Some(ny) = wrap_coordinate(y as i32 + dy, self.height, self.wrap.wraps_y()) else {
+                continue;
+            };
+
+            neighbors.push((usize::from(transpose(d)), self.pos_to_idx((nx, ny))));
+        }
+
+        neighbors
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        usize::from(Direction::from(direction).opposite())
+    }
+}
+
+/// A topology with some of its cells left out, such as a grid with holes
+/// in it.
+///
+/// The kept cells are numbered in the order they have in the inner
+/// topology, and edges leading to a left out cell are dropped.
+#[derive(Debug, Clone)]
+pub struct Masked<T> {
+    inner: T,
+    /// The index in the inner topology of each kept cell.
+    cells: Vec<usize>,
+    /// The index of each cell of the inner topology, if it's kept.
+    index: Vec<Option<usize>>,
+}
+
+impl<T: Topology> Masked<T> {
+    /// Keeps the cells of `inner` whose entry in `mask` is `true`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mask` doesn't have an entry for every cell.
+    pub fn new(inner: T, mask: &[bool]) -> Self {
+        assert_eq!(mask.len(), inner.len());
+
+        let cells = (0..mask.len()).filter(|&idx| mask[idx]).collect::<Vec<_>>();
+        let mut index = vec![None; mask.len()];
+        for (idx, &cell) in cells.iter().enumerate() {
+            index[cell] = Some(idx);
+        }
+
+        Masked {
+            inner,
+            cells,
+            index,
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// The index in the inner topology of the cell at `idx`.
+    pub fn cell(&self, idx: usize) -> usize {
+        self.cells[idx]
+    }
+
+    /// The index of the cell at `cell` in the inner topology, or `None` if
+    /// it's left out.
+    pub fn find(&self, cell: usize) -> Option<usize> {
+        self.index[cell]
+    }
+}
+
+impl<T: Topology> Topology for Masked<T> {
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn directions(&self) -> usize {
+        self.inner.directions()
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        self.inner
+            .neighbors(self.cells[idx])
+            .into_iter()
+            .filter_map(|(d, neighbor)| Some((d, self.index[neighbor]?)))
+            .collect()
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        self.inner.opposite(direction)
+    }
+}
+
+/// A topology given by an explicit adjacency list, such as the rooms of a
+/// dungeon and the doors between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphTopology {
+    /// The label of the edge leading back, for each label.
+    opposites: Vec<usize>,
+    /// The label and target of the edges leaving each cell.
+    adjacency: Vec<Vec<(usize, usize)>>,
+}
+
+impl GraphTopology {
+    /// Builds the topology, where `opposites[d]` is the label of the edge
+    /// leading back along an edge labeled `d`, and `adjacency[idx]` lists
+    /// the label and target of every edge leaving the cell at `idx`.
+    ///
+    /// A label can be its own opposite, as with the doors of a dungeon
+    /// that have no particular direction.
+    ///
+    /// Fails if a label or a cell is out of range, if an edge appears twice
+    /// or if an edge has no edge leading back.
+    pub fn new(
+        opposites: Vec<usize>,
+        adjacency: Vec<Vec<(usize, usize)>>,
+    ) -> Result<Self, WfcError> {
+        let directions = opposites.len();
+        for (d, &opposite) in opposites.iter().enumerate() {
+            if opposites.get(opposite) != Some(&d) {
+                return Err(invalid(format!(
+                    "the opposite of label {d} doesn't lead back to it"
+                )));
+            }
+        }
+
+        let edges = adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, edges)| edges.iter().map(move |&(d, neighbor)| (idx, d, neighbor)))
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::default();
+        for &(idx, d, neighbor) in &edges {
+            if d >= directions {
+                return Err(invalid(format!("cell {idx} has an edge labeled {d}")));
+            }
+            if neighbor >= adjacency.len() {
+                return Err(invalid(format!("cell {idx} leads to cell {neighbor}")));
+            }
+            if !seen.insert((idx, d, neighbor)) {
+                return Err(invalid(format!(
+                    "cell {idx} leads to cell {neighbor} twice with label {d}"
+                )));
+            }
+        }
+
+        for &(idx, d, neighbor) in &edges {
+            if !seen.contains(&(neighbor, opposites[d], idx)) {
+                return Err(invalid(format!(
+                    "cell {neighbor} doesn't lead back to cell {idx} with label {}",
+                    opposites[d]
+                )));
+            }
+        }
+
+        Ok(GraphTopology {
+            opposites,
+            adjacency,
+        })
+    }
+}
+
+impl Topology for GraphTopology {
+    fn len(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    fn directions(&self) -> usize {
+        self.opposites.len()
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        self.adjacency[idx].clone()
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        self.opposites[direction]
+    }
+}
+
+fn invalid(message: String) -> WfcError {
+    WfcError::InvalidTopology { message }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{GraphTopology, Grid, Masked, Topology};
+    use crate::compatibility::Compatibility;
+    use crate::direction::{transpose, Direction};
+    use crate::error::WfcError;
+    use crate::table::{Table, Wrap};
+    use crate::wfc::Solver;
+
+    /// Patterns that can only be put next to a different pattern, along
+    /// edges with any of the `directions` labels.
+    fn different(len: usize, directions: usize) -> Compatibility {
+        let mut compatibility = Compatibility::with_directions(len, directions);
+        for (p1, p2) in (0..len).cartesian_product(0..len) {
+            for d in 0..directions {
+                if p1 != p2 {
+                    compatibility.insert(p1, p2, d);
+                }
+            }
+        }
+
+        compatibility
+    }
+
+    /// Checks that every edge of `topology` joins different patterns.
+    fn assert_different(topology: &impl Topology, ids: &[usize]) {
+        assert_eq!(ids.len(), topology.len());
+        for idx in 0..topology.len() {
+            for (_, neighbor) in topology.neighbors(idx) {
+                assert_ne!(ids[idx], ids[neighbor]);
+            }
+        }
+    }
+
+    #[test]
+    fn grid() {
+        // [0, 1, 2]
+        // [3, 4, 5]
+        let grid = Grid::new(3, 2);
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid.idx_to_pos(5), (2, 1));
+        // Like the constraints tables, `Up` leads to the previous column
+        // and `Left` to the previous row.
+        assert_eq!(
+            grid.neighbors(1),
+            vec![
+                (usize::from(Direction::Down), 2),
+                (usize::from(Direction::Right), 4),
+                (usize::from(Direction::Up), 0),
+            ]
+        );
+        assert_eq!(grid.opposite(usize::from(Direction::Up)), 2);
+
+        let grid = grid.with_wrap(Wrap::Horizontal);
+        assert_eq!(
+            grid.neighbors(3).into_iter().map(|(_, n)| n).collect_vec(),
+            vec![0, 4, 5]
+        );
+    }
+
+    #[test]
+    fn grid_matches_table() {
+        // Pattern `0` can only have pattern `1` on its right, and pattern
+        // `2` can only have pattern `3` below it, as seen in the output.
+        let mut compatibility = Compatibility::new(4);
+        compatibility.insert(0, 1, transpose(Direction::Right));
+        compatibility.insert(1, 0, transpose(Direction::Left));
+        compatibility.insert(2, 3, transpose(Direction::Down));
+        compatibility.insert(3, 2, transpose(Direction::Up));
+
+        let solver = Solver::new(compatibility.clone(), vec![1.0, 1.0, 0.0, 0.0]);
+        let rows = Table::new(vec![(); 2], 1);
+        assert_eq!(solver.solve(&Grid::new(2, 1), 0), Ok(vec![0, 1]));
+        assert_eq!(solver.solve(&rows, 0), Ok(vec![0, 1]));
+
+        let solver = Solver::new(compatibility, vec![0.0, 0.0, 1.0, 1.0]);
+        let columns = Table::new(vec![(); 2], 2);
+        assert_eq!(solver.solve(&Grid::new(1, 2), 0), Ok(vec![2, 3]));
+        assert_eq!(solver.solve(&columns, 0), Ok(vec![2, 3]));
+    }
+
+    #[test]
+    fn masked() {
+        // [0, 1, 2]
+        // [3, #, 4]
+        // [5, 6, 7]
+        let mut mask = vec![true; 9];
+        mask[4] = false;
+        let ring = Masked::new(Grid::new(3, 3), &mask);
+        assert_eq!(ring.len(), 8);
+        assert_eq!(ring.cell(4), 5);
+        assert_eq!(ring.find(5), Some(4));
+        assert_eq!(ring.find(4), None);
+        assert_eq!(
+            ring.neighbors(1).into_iter().map(|(_, n)| n).collect_vec(),
+            vec![2, 0]
+        );
+
+        // A checkerboard fits around the hole.
+        let solver = Solver::new(different(2, 4), vec![1.0; 2]);
+        for seed in 0..4 {
+            let ids = solver.solve(&ring, seed).unwrap();
+            assert_different(&ring, &ids);
+        }
+    }
+
+    #[test]
+    fn graph() {
+        // Five rooms in a ring, with a door across it, where every door
+        // has the same label.
+        let mut adjacency = vec![Vec::new(); 5];
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 4), (4, 0), (0, 2)] {
+            adjacency[a].push((0, b));
+            adjacency[b].push((0, a));
+        }
+        let rooms = GraphTopology::new(vec![0], adjacency).unwrap();
+        assert_eq!(rooms.len(), 5);
+        assert_eq!(rooms.neighbors(0), vec![(0, 1), (0, 4), (0, 2)]);
+
+        let solver = Solver::new(different(3, 1), vec![1.0; 3]);
+        for seed in 0..8 {
+            let ids = solver.solve(&rooms, seed).unwrap();
+            assert_different(&rooms, &ids);
+            assert_eq!(ids, solver.solve(&rooms, seed).unwrap());
+        }
+
+        // Two colors can't fill a triangle.
+        let solver = Solver::new(different(2, 1), vec![1.0; 2]).with_backtrack_limit(2);
+        assert!(matches!(
+            solver.solve(&rooms, 0),
+            Err(WfcError::NodeContradiction { .. } | WfcError::NodeBacktrackLimitReached { .. })
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let invalid = |opposites: Vec<usize>, adjacency: Vec<Vec<(usize, usize)>>| {
+            matches!(
+                GraphTopology::new(opposites, adjacency),
+                Err(WfcError::InvalidTopology { .. })
+            )
+        };
+
+        // Labels 0 and 1 lead back along each other.
+        assert!(!invalid(vec![1, 0], vec![vec![(0, 1)], vec![(1, 0)]]));
+        assert!(invalid(vec![1, 1], vec![]));
+        assert!(invalid(vec![2], vec![]));
+        assert!(invalid(vec![1, 0], vec![vec![(2, 1)], vec![(1, 0)]]));
+        assert!(invalid(vec![1, 0], vec![vec![(0, 2)], vec![(1, 0)]]));
+        assert!(invalid(vec![1, 0], vec![vec![(0, 1)], vec![(0, 0)]]));
+        assert!(invalid(
+            vec![1, 0],
+            vec![vec![(0, 1), (0, 1)], vec![(1, 0), (1, 0)]]
+        ));
+    }
+}
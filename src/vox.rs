@@ -0,0 +1,310 @@
+use crate::error::WfcError;
+use crate::voxel::VoxelGrid;
+
+/// The version written to the header of new files.
+const VERSION: u32 = 150;
+
+/// The largest size of a model along any axis, since voxel coordinates are
+/// stored in a byte.
+const MAX_SIZE: usize = 256;
+
+/// Parses the first model of a MagicaVoxel `.vox` file, along with its
+/// palette.
+///
+/// Scene graphs, materials and every other chunk are skipped.
+pub fn read(bytes: &[u8]) -> Result<VoxelGrid, WfcError> {
+    if bytes.get(..4) != Some(b"VOX ") {
+        return Err(invalid("the file doesn't start with \"VOX \""));
+    }
+
+    let main = chunk(bytes, 8)?;
+    if main.id != b"MAIN" {
+        return Err(invalid("the first chunk isn't MAIN"));
+    }
+
+    let mut size = None;
+    let mut grid = None;
+    let mut palette = None;
+    let mut offset = 0;
+    while offset < main.children.len() {
+        let Chunk {
+            id, content, end, ..
+        } = chunk(main.children, offset)?;
+        match id {
+            b"SIZE" if size.is_none() => {
+                let axis = |i: usize| u32_at(content, 4 * i).map(|len| len as usize);
+                let (width, depth, height) = (axis(0)?, axis(1)?, axis(2)?);
+                check_size((width, depth, height))?;
+                size = Some((width, depth, height));
+            }
+            b"XYZI" if grid.is_none() => {
+                let size = size.ok_or_else(|| invalid("XYZI comes before SIZE"))?;
+                grid = Some(voxels(content, size)?);
+            }
+            b"RGBA" if palette.is_none() => {
+                if content.len() < 4 * 256 {
+                    return Err(invalid("the palette is too short"));
+                }
+
+                // The first entry holds the color of index `1`.
+                let mut colors = vec![[0; 4]; 256];
+                for (i, rgba) in content.chunks_exact(4).take(255).enumerate() {
+                    colors[i + 1].copy_from_slice(rgba);
+                }
+                palette = Some(colors);
+            }
+            _ => {}
+        }
+
+        offset = end;
+    }
+
+    let grid = grid.ok_or_else(|| invalid("there is no model"))?;
+    match palette {
+        Some(palette) => grid.with_palette(palette),
+        None => Ok(grid),
+    }
+}
+
+/// Encodes a grid as a MagicaVoxel `.vox` file with a single model.
+///
+/// Fails if the grid is empty or larger than 256 voxels along any axis.
+pub fn write(grid: &VoxelGrid) -> Result<Vec<u8>, WfcError> {
+    let (width, depth, height) = grid.size();
+    check_size((width, depth, height))?;
+
+    let mut size = Vec::with_capacity(12);
+    for len in [width, depth, height] {
+        size.extend((len as u32).to_le_bytes());
+    }
+
+    let mut xyzi = vec![0; 4];
+    let mut count = 0u32;
+    for z in 0..height {
+        for y in 0..depth {
+            for x in 0..width {
+                let color = grid.get((x, y, z));
+                if color != 0 {
+                    xyzi.extend([x as u8, y as u8, z as u8, color]);
+                    count += 1;
+                }
+            }
+        }
+    }
+    xyzi[..4].copy_from_slice(&count.to_le_bytes());
+
+    let mut children = Vec::new();
+    push_chunk(&mut children, b"SIZE", &size, &[]);
+    push_chunk(&mut children, b"XYZI", &xyzi, &[]);
+    if let Some(palette) = grid.palette() {
+        let rgba = palette[1..]
+            .iter()
+            .chain([&[0; 4]])
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        push_chunk(&mut children, b"RGBA", &rgba, &[]);
+    }
+
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+    push_chunk(&mut bytes, b"MAIN", &[], &children);
+
+    Ok(bytes)
+}
+
+/// Checks that a model of the given size can be stored in a file, which
+/// needs at least one voxel and at most 256 along each axis.
+fn check_size((width, depth, height): (usize, usize, usize)) -> Result<(), WfcError> {
+    if [width, depth, height]
+        .iter()
+        .any(|&len| len == 0 || len > MAX_SIZE)
+    {
+        return Err(invalid(format!(
+            "a {width}x{depth}x{height} model can't be stored in a file"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Reads the voxels of an `XYZI` chunk into a grid of the given size.
+fn voxels(content: &[u8], size: (usize, usize, usize)) -> Result<VoxelGrid, WfcError> {
+    let count = u32_at(content, 0)? as usize;
+    let voxels = content
+        .get(4..)
+        .and_then(|voxels| voxels.get(..count.checked_mul(4)?))
+        .ok_or_else(|| invalid("there are fewer voxels than announced"))?;
+
+    let mut grid = VoxelGrid::new(size);
+    for voxel in voxels.chunks_exact(4) {
+        let (x, y, z) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize);
+        if x >= size.0 || y >= size.1 || z >= size.2 {
+            return Err(invalid(format!(
+                "the voxel at {:?} is outside the model",
+                (x, y, z)
+            )));
+        }
+
+        grid.set((x, y, z), voxel[3]);
+    }
+
+    Ok(grid)
+}
+
+/// A chunk of a `.vox` file.
+struct Chunk<'a> {
+    id: &'a [u8],
+    content: &'a [u8],
+    /// The chunks nested in this one.
+    children: &'a [u8],
+    /// The offset right after the chunk, where the next one starts.
+    end: usize,
+}
+
+/// Reads the chunk at `offset`.
+fn chunk(bytes: &[u8], offset: usize) -> Result<Chunk<'_>, WfcError> {
+    let id = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| invalid("a chunk is cut short"))?;
+    let content_len = u32_at(bytes, offset + 4)? as usize;
+    let children_len = u32_at(bytes, offset + 8)? as usize;
+
+    let start = offset + 12;
+    let middle = start + content_len;
+    let end = middle + children_len;
+    if end > bytes.len() {
+        return Err(invalid(format!(
+            "the {} chunk is cut short",
+            String::from_utf8_lossy(id)
+        )));
+    }
+
+    Ok(Chunk {
+        id,
+        content: &bytes[start..middle],
+        children: &bytes[middle..end],
+        end,
+    })
+}
+
+fn push_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend(id);
+    bytes.extend((content.len() as u32).to_le_bytes());
+    bytes.extend((children.len() as u32).to_le_bytes());
+    bytes.extend(content);
+    bytes.extend(children);
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, WfcError> {
+    let bytes = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| invalid("a chunk is cut short"))?;
+
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn invalid(message: impl Into<String>) -> WfcError {
+    WfcError::InvalidVox {
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::error::WfcError;
+    use crate::voxel::VoxelGrid;
+
+    #[test]
+    fn round_trip() {
+        let mut grid = VoxelGrid::new((3, 2, 4));
+        grid.set((0, 0, 0), 1);
+        grid.set((2, 1, 3), 7);
+        grid.set((1, 0, 2), 255);
+        assert_eq!(read(&write(&grid).unwrap()), Ok(grid.clone()));
+
+        let mut palette = vec![[0; 4]; 256];
+        palette[1] = [255, 0, 0, 255];
+        palette[255] = [0, 0, 255, 255];
+        let grid = grid.with_palette(palette).unwrap();
+        assert_eq!(read(&write(&grid).unwrap()), Ok(grid));
+    }
+
+    #[test]
+    fn round_trip_sizes() {
+        // Every grid that can be written is read back, and the others are
+        // rejected the same way they would be when reading them.
+        let sizes = [
+            (1, 1, 1),
+            (256, 2, 1),
+            (0, 2, 2),
+            (2, 0, 2),
+            (2, 2, 0),
+            (257, 1, 1),
+        ];
+        for size in sizes {
+            let grid = VoxelGrid::new(size);
+            let fits = [size.0, size.1, size.2]
+                .iter()
+                .all(|len| (1..=256).contains(len));
+            match write(&grid) {
+                Ok(bytes) => {
+                    assert!(fits, "{size:?}");
+                    assert_eq!(read(&bytes), Ok(grid));
+                }
+                Err(err) => {
+                    assert!(!fits, "{size:?}");
+                    assert!(matches!(err, WfcError::InvalidVox { .. }));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let mut grid = VoxelGrid::new((1, 1, 1));
+        grid.set((0, 0, 0), 3);
+        let mut bytes = write(&grid).unwrap();
+
+        // A PACK chunk before the model, which grows MAIN's children.
+        let pack = [
+            b"PACK".as_slice(),
+            &4u32.to_le_bytes(),
+            &[0; 4],
+            &[1, 0, 0, 0],
+        ]
+        .concat();
+        bytes.splice(20..20, pack);
+        let children = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) + 16;
+        bytes[16..20].copy_from_slice(&children.to_le_bytes());
+        assert_eq!(read(&bytes), Ok(grid));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(read(b"PNG "), Err(WfcError::InvalidVox { .. })));
+
+        let mut grid = VoxelGrid::new((2, 2, 2));
+        grid.set((1, 1, 1), 1);
+        let bytes = write(&grid).unwrap();
+        assert!(matches!(
+            read(&bytes[..bytes.len() - 1]),
+            Err(WfcError::InvalidVox { .. })
+        ));
+
+        // A voxel outside the model.
+        let mut outside = bytes.clone();
+        let last = outside.len() - 4;
+        outside[last] = 2;
+        assert!(matches!(read(&outside), Err(WfcError::InvalidVox { .. })));
+
+        // Models that are empty or too large for voxel coordinates, which
+        // are rejected before allocating them.
+        for len in [0, 257, 100_000] {
+            let mut size = bytes.clone();
+            size[32..36].copy_from_slice(&(len as u32).to_le_bytes());
+            assert!(matches!(read(&size), Err(WfcError::InvalidVox { .. })));
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use std::path::Path;
+
+use itertools::iproduct;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::compatibility::Compatibility;
+use crate::direction::Direction3;
+use crate::error::WfcError;
+use crate::pattern;
+use crate::table::{wrap_coordinate, Wrap};
+use crate::topology::Topology;
+use crate::vox;
+use crate::wfc::{solve_graph, Graph, DEFAULT_BACKTRACK_LIMIT};
+
+/// A box of voxels, each holding the index of its color in a palette.
+///
+/// Index `0` is an empty voxel. Positions are `(x, y, z)`, with the `z`
+/// axis pointing up, like in MagicaVoxel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxelGrid {
+    /// The amount of voxels along the `x`, `y` and `z` axes.
+    size: (usize, usize, usize),
+    /// The voxels, indexed by `x + width * (y + depth * z)`.
+    voxels: Vec<u8>,
+    /// The color of each index, if the grid has a palette of its own.
+    palette: Option<Vec<[u8; 4]>>,
+}
+
+impl VoxelGrid {
+    /// Creates an empty grid with the given size.
+    pub fn new(size: (usize, usize, usize)) -> Self {
+        VoxelGrid {
+            size,
+            voxels: vec![0; size.0 * size.1 * size.2],
+            palette: None,
+        }
+    }
+
+    /// Sets the RGBA color of each of the 256 indices, failing if the
+    /// palette doesn't have exactly 256 colors.
+    ///
+    /// Grids without a palette use MagicaVoxel's default one.
+    pub fn with_palette(mut self, palette: Vec<[u8; 4]>) -> Result<Self, WfcError> {
+        if palette.len() != 256 {
+            return Err(WfcError::InvalidVox {
+                message: format!("a palette has 256 colors, not {}", palette.len()),
+            });
+        }
+
+        self.palette = Some(palette);
+        Ok(self)
+    }
+
+    /// Reads the first model of a MagicaVoxel `.vox` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WfcError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+
+        vox::read(&bytes)
+    }
+
+    /// Writes the grid to a MagicaVoxel `.vox` file.
+    ///
+    /// Fails if the grid is larger than 256 voxels along any axis, which
+    /// MagicaVoxel can't store.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), WfcError> {
+        let path = path.as_ref();
+        std::fs::write(path, vox::write(self)?).map_err(|err| WfcError::Io {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })
+    }
+
+    pub fn size(&self) -> (usize, usize, usize) {
+        self.size
+    }
+
+    pub fn palette(&self) -> Option<&[[u8; 4]]> {
+        self.palette.as_deref()
+    }
+
+    pub fn get(&self, pos: (usize, usize, usize)) -> u8 {
+        self.voxels[self.pos_to_idx(pos)]
+    }
+
+    pub fn set(&mut self, pos: (usize, usize, usize), color: u8) {
+        let idx = self.pos_to_idx(pos);
+        self.voxels[idx] = color;
+    }
+
+    fn pos_to_idx(&self, (x, y, z): (usize, usize, usize)) -> usize {
+        let (width, depth, height) = self.size;
+        assert!(x < width && y < depth && z < height, "out of bounds");
+        x + width * (y + depth * z)
+    }
+
+    fn idx_to_pos(&self, idx: usize) -> (usize, usize, usize) {
+        let (width, depth, _) = self.size;
+        (idx % width, idx / width % depth, idx / (width * depth))
+    }
+}
+
+/// The overlapping model in three dimensions.
+///
+/// It extracts every `NxNxN` pattern of a voxel sample, and fills the output
+/// with patterns that overlap their neighbors in each of the six
+/// directions.
+pub struct VoxelModel {
+    /// The voxels of each pattern, indexed by `x + N * (y + N * z)`.
+    patterns: Vec<Vec<u8>>,
+    /// The amount of times each pattern appears in the sample.
+    frequencies: Vec<usize>,
+    /// The patterns that overlap each pattern in each direction.
+    compatibility: Compatibility,
+    /// The palette of the sample, passed on to the outputs.
+    palette: Option<Vec<[u8; 4]>>,
+    /// The maximum amount of times the solver may backtrack in a single
+    /// generation.
+    backtrack_limit: usize,
+    /// The horizontal axes along which the output wraps around.
+    wrap: Wrap,
+}
+
+impl VoxelModel {
+    /// Extracts the patterns of size `pattern_size` from the sample, which
+    /// wraps around its borders if `periodic_input` is set.
+    pub fn new(
+        sample: &VoxelGrid,
+        pattern_size: usize,
+        periodic_input: bool,
+    ) -> Result<Self, WfcError> {
+        if pattern_size == 0 {
+            return Err(WfcError::ZeroPatternSize);
+        }
+
+        let (width, depth, height) = sample.size();
+        let fits = pattern_size <= width && pattern_size <= depth && pattern_size <= height;
+        if !periodic_input && !fits {
+            return Err(WfcError::VoxelPatternTooLarge {
+                pattern_size,
+                size: sample.size(),
+            });
+        }
+
+        let positions = |len: usize| {
+            if periodic_input {
+                0..len
+            } else {
+                0..len - pattern_size + 1
+            }
+        };
+
+        let windows = iproduct!(positions(height), positions(depth), positions(width))
+            .map(|(z, y, x)| extract(sample, pattern_size, (x, y, z)));
+        let (patterns, frequencies) = pattern::count(windows);
+
+        let compatibility = build_constraints(&patterns, pattern_size);
+
+        Ok(VoxelModel {
+            patterns,
+            frequencies,
+            compatibility,
+            palette: sample.palette.clone(),
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            wrap: Wrap::None,
+        })
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Sets the axes along which the output wraps around. `Horizontal`
+    /// wraps the `x` axis and `Vertical` the `y` axis, while the `z` axis
+    /// never wraps.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// The amount of distinct patterns in the sample.
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Generates a grid of voxels of the given size.
+    ///
+    /// The same seed always produces the same output for the same sample.
+    pub fn generate(&self, size: (usize, usize, usize), seed: u64) -> Result<VoxelGrid, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(size, &mut rng)
+    }
+
+    /// Like [`VoxelModel::generate`], but draws every random choice from
+    /// `rng`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        size: (usize, usize, usize),
+        rng: &mut R,
+    ) -> Result<VoxelGrid, WfcError> {
+        if size.0 == 0 || size.1 == 0 || size.2 == 0 {
+            return Err(WfcError::ZeroVoxelOutputSize { size });
+        }
+
+        let mut output = VoxelGrid::new(size);
+        output.palette = self.palette.clone();
+
+        let cells = Voxels {
+            size,
+            wraps: [self.wrap.wraps_x(), self.wrap.wraps_y(), false],
+        };
+        let graph = Graph::new(&cells);
+        let weights = self.frequencies.iter().map(|&f| f as f64).collect();
+        let ids = solve_graph(
+            &self.compatibility,
+            weights,
+            &graph,
+            self.backtrack_limit,
+            rng,
+        )
+        .map_err(|failure| failure.into_voxel_error(|slot| output.idx_to_pos(slot)))?;
+
+        for (voxel, id) in output.voxels.iter_mut().zip(ids) {
+            *voxel = self.patterns[id][0];
+        }
+
+        Ok(output)
+    }
+}
+
+/// The voxels of an output, each connected to its neighbors in the six
+/// directions.
+struct Voxels {
+    size: (usize, usize, usize),
+    /// Whether each axis wraps around.
+    wraps: [bool; 3],
+}
+
+impl Topology for Voxels {
+    fn len(&self) -> usize {
+        self.size.0 * self.size.1 * self.size.2
+    }
+
+    fn directions(&self) -> usize {
+        6
+    }
+
+    fn neighbors(&self, idx: usize) -> Vec<(usize, usize)> {
+        let (width, depth, height) = self.size;
+        let (x, y, z) = (idx % width, idx / width % depth, idx / (width * depth));
+        Direction3::all()
+            .into_iter()
+            .filter_map(|d| {
+                let (dx, dy, dz) = d.offset();
+                let x = wrap_coordinate(x as i32 + dx, width, self.wraps[0])?;
+                let y = wrap_coordinate(y as i32 + dy, depth, self.wraps[1])?;
+                let z = wrap_coordinate(z as i32 + dz, height, self.wraps[2])?;
+                Some((usize::from(d), x + width * (y + depth * z)))
+            })
+            .collect()
+    }
+
+    fn opposite(&self, direction: usize) -> usize {
+        usize::from(Direction3::from(direction).opposite())
+    }
+}
+
+/// Returns the `size`x`size`x`size` pattern with its lowest corner at the
+/// given position, wrapping around the borders of the sample.
+fn extract(sample: &VoxelGrid, size: usize, (x, y, z): (usize, usize, usize)) -> Vec<u8> {
+    let (width, depth, height) = sample.size();
+    let mut pattern = Vec::with_capacity(size * size * size);
+    for dz in 0..size {
+        for dy in 0..size {
+            for dx in 0..size {
+                let pos = ((x + dx) % width, (y + dy) % depth, (z + dz) % height);
+                pattern.push(sample.get(pos));
+            }
+        }
+    }
+
+    pattern
+}
+
+/// Returns the voxels of a pattern that overlap the pattern next to it in
+/// the given direction, which are all but the layer facing away from it.
+fn side(pattern: &[u8], size: usize, direction: Direction3) -> Vec<u8> {
+    let (dx, dy, dz) = direction.offset();
+    let overlaps = |coordinate: usize, offset: i32| match offset {
+        1 => coordinate > 0,
+        -1 => coordinate < size - 1,
+        _ => true,
+    };
+
+    let mut side = Vec::with_capacity(size * size * (size - 1));
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                if overlaps(x, dx) && overlaps(y, dy) && overlaps(z, dz) {
+                    side.push(pattern[x + size * (y + size * z)]);
+                }
+            }
+        }
+    }
+
+    side
+}
+
+/// Builds the constraints table, where a pattern is compatible with another
+/// in a direction if they overlap when the second one is moved a voxel in
+/// that direction.
+fn build_constraints(patterns: &[Vec<u8>], size: usize) -> Compatibility {
+    Compatibility::overlapping(
+        patterns.iter().enumerate(),
+        &Direction3::all(),
+        |d| d.opposite(),
+        |pattern, d| side(pattern, size, d),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::iproduct;
+
+    use super::{VoxelGrid, VoxelModel};
+    use crate::direction::Direction3;
+    use crate::error::WfcError;
+    use crate::table::Wrap;
+    use crate::test_utils::assert_deterministic;
+
+    /// A sample whose voxels cycle through the colors `1`, `2` and `3`
+    /// along the `x` axis, and are the same along the other axes.
+    fn stripes() -> VoxelGrid {
+        let mut sample = VoxelGrid::new((3, 2, 2));
+        for (x, y, z) in iproduct!(0..3, 0..2, 0..2) {
+            sample.set((x, y, z), 1 + x as u8);
+        }
+        sample
+    }
+
+    /// Checks that the voxels of the output follow the stripes.
+    fn assert_striped(output: &VoxelGrid) {
+        let (width, depth, height) = output.size();
+        for (x, y, z) in iproduct!(0..width, 0..depth, 0..height) {
+            let color = output.get((x, y, z));
+            assert_eq!(color, output.get((x, 0, 0)));
+            if x + 1 < width {
+                assert_eq!(output.get((x + 1, y, z)), color % 3 + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn grid() {
+        let mut grid = VoxelGrid::new((2, 3, 4));
+        assert_eq!(grid.size(), (2, 3, 4));
+        assert_eq!(grid.get((1, 2, 3)), 0);
+
+        grid.set((1, 2, 3), 5);
+        assert_eq!(grid.get((1, 2, 3)), 5);
+        assert_eq!(grid.voxels.iter().filter(|&&v| v != 0).count(), 1);
+        for idx in 0..grid.voxels.len() {
+            assert_eq!(grid.pos_to_idx(grid.idx_to_pos(idx)), idx);
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        let model = VoxelModel::new(&stripes(), 2, true).unwrap();
+        assert_eq!(model.len(), 3);
+        assert_eq!(model.frequencies, vec![4, 4, 4]);
+
+        // Each pattern only overlaps the next one along the `x` axis, and
+        // itself along the others.
+        for p1 in 0..3 {
+            let next = (p1 + 1) % 3;
+            for p2 in 0..3 {
+                let compatibility = &model.compatibility;
+                assert_eq!(
+                    compatibility.contains(p1, p2, Direction3::Right),
+                    p2 == next
+                );
+                assert_eq!(compatibility.contains(p2, p1, Direction3::Left), p2 == next);
+                for d in [Direction3::Front, Direction3::Back, Direction3::Up] {
+                    assert_eq!(compatibility.contains(p1, p2, d), p1 == p2);
+                }
+            }
+        }
+
+        let model = VoxelModel::new(&stripes(), 2, false).unwrap();
+        assert_eq!(model.len(), 2);
+    }
+
+    #[test]
+    fn generate() {
+        let model = VoxelModel::new(&stripes(), 2, true).unwrap();
+        for output in assert_deterministic(|seed| model.generate((5, 4, 3), seed)) {
+            assert_eq!(output.size(), (5, 4, 3));
+            assert_striped(&output);
+        }
+
+        // The stripes can only wrap around if the width is a multiple of 3.
+        let model = model.with_wrap(Wrap::Both);
+        assert_striped(&model.generate((6, 2, 2), 0).unwrap());
+        assert!(model.generate((4, 2, 2), 0).is_err());
+    }
+
+    #[test]
+    fn generate_keeps_palette() {
+        let mut palette = vec![[0; 4]; 256];
+        palette[1] = [255, 0, 0, 255];
+        let sample = stripes().with_palette(palette.clone()).unwrap();
+        let model = VoxelModel::new(&sample, 1, true).unwrap();
+        let output = model.generate((2, 2, 2), 0).unwrap();
+        assert_eq!(output.palette(), Some(palette.as_slice()));
+        assert!(output.voxels.iter().all(|v| (1..=3).contains(v)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            VoxelModel::new(&stripes(), 0, true).err(),
+            Some(WfcError::ZeroPatternSize)
+        );
+        assert_eq!(
+            VoxelModel::new(&stripes(), 3, false).err(),
+            Some(WfcError::VoxelPatternTooLarge {
+                pattern_size: 3,
+                size: (3, 2, 2)
+            })
+        );
+
+        let model = VoxelModel::new(&stripes(), 2, true).unwrap();
+        assert_eq!(
+            model.generate((2, 0, 2), 0),
+            Err(WfcError::ZeroVoxelOutputSize { size: (2, 0, 2) })
+        );
+
+        let model = model.with_wrap(Wrap::Horizontal).with_backtrack_limit(0);
+        assert!(matches!(
+            model.generate((4, 1, 1), 0),
+            Err(WfcError::VoxelContradiction { .. } | WfcError::VoxelBacktrackLimitReached { .. })
+        ));
+
+        let empty = VoxelModel::new(&VoxelGrid::new((0, 0, 0)), 1, true).unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.generate((1, 1, 1), 0), Err(WfcError::NoPatterns));
+    }
+}
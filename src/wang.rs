@@ -0,0 +1,287 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rustc_hash::FxHashMap as HashMap;
+
+use crate::compatibility::Compatibility;
+use crate::direction::{transpose, Direction};
+use crate::error::WfcError;
+use crate::table::Wrap;
+use crate::tiles::{check_tiles, TileSolver};
+
+/// The labels on the corners of a tile, usually the terrain each corner is
+/// made of.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Corners {
+    pub top_left: String,
+    pub top_right: String,
+    pub bottom_right: String,
+    pub bottom_left: String,
+}
+
+impl Corners {
+    /// Creates the corners in clockwise order, starting from the top left.
+    pub fn new(
+        top_left: impl Into<String>,
+        top_right: impl Into<String>,
+        bottom_right: impl Into<String>,
+        bottom_left: impl Into<String>,
+    ) -> Self {
+        Corners {
+            top_left: top_left.into(),
+            top_right: top_right.into(),
+            bottom_right: bottom_right.into(),
+            bottom_left: bottom_left.into(),
+        }
+    }
+
+    /// The two corners on the side of the tile facing the given direction,
+    /// from left to right or from top to bottom.
+    ///
+    /// Directions are named as seen in the output, so `Up` is the top side.
+    pub fn side(&self, direction: Direction) -> (&str, &str) {
+        match direction {
+            Direction::Up => (&self.top_left, &self.top_right),
+            Direction::Right => (&self.top_right, &self.bottom_right),
+            Direction::Down => (&self.bottom_left, &self.bottom_right),
+            Direction::Left => (&self.top_left, &self.bottom_left),
+        }
+    }
+}
+
+/// A tile of the Wang model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WangTile {
+    pub name: String,
+    pub corners: Corners,
+    /// How likely the tile is to be chosen, relative to the other tiles.
+    pub weight: f64,
+}
+
+impl WangTile {
+    pub fn new(name: impl Into<String>, corners: Corners) -> Self {
+        WangTile {
+            name: name.into(),
+            corners,
+            weight: 1.0,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Creates a tile for every way of labeling the four corners with the
+    /// given terrains, `terrains.len()⁴` tiles in total.
+    ///
+    /// Each tile is named after its corners, clockwise from the top left
+    /// and joined by `-`, like `grass-grass-water-grass`.
+    pub fn complete_set(terrains: &[&str]) -> Vec<WangTile> {
+        let mut tiles = Vec::with_capacity(terrains.len().pow(4));
+        for &top_left in terrains {
+            for &top_right in terrains {
+                for &bottom_right in terrains {
+                    for &bottom_left in terrains {
+                        let name = [top_left, top_right, bottom_right, bottom_left].join("-");
+                        let corners = Corners::new(top_left, top_right, bottom_right, bottom_left);
+                        tiles.push(WangTile::new(name, corners));
+                    }
+                }
+            }
+        }
+
+        tiles
+    }
+}
+
+/// The corner-labeled Wang tile model, also known as marching squares.
+///
+/// It fills a grid with tiles, so that every pair of adjacent tiles agrees
+/// on the labels of the two corners they share. Unlike sockets on the
+/// sides, this makes the tiles meeting at a point agree on its terrain.
+pub struct WangModel {
+    tiles: Vec<WangTile>,
+    /// The tiles that can be put next to each tile, and how to fill a grid
+    /// with them.
+    solver: TileSolver,
+}
+
+impl WangModel {
+    /// Builds the model, failing if two tiles share a name or a weight is
+    /// negative.
+    pub fn new(tiles: Vec<WangTile>) -> Result<Self, WfcError> {
+        let weights = check_tiles(tiles.iter().map(|tile| (tile.name.as_str(), tile.weight)))?;
+
+        // The tiles with each pair of corners on each side, so that a
+        // complete set doesn't need to compare every pair of tiles.
+        let mut sides: [HashMap<(&str, &str), Vec<usize>>; 4] = Default::default();
+        for d in Direction::all() {
+            for (t, tile) in tiles.iter().enumerate() {
+                sides[usize::from(d)]
+                    .entry(tile.corners.side(d))
+                    .or_default()
+                    .push(t);
+            }
+        }
+
+        let mut compatibility = Compatibility::new(tiles.len());
+        for (t1, tile) in tiles.iter().enumerate() {
+            for d in Direction::all() {
+                let facing = &sides[usize::from(d.opposite())];
+                for &t2 in facing.get(&tile.corners.side(d)).into_iter().flatten() {
+                    compatibility.insert(t1, t2, transpose(d));
+                }
+            }
+        }
+
+        Ok(WangModel {
+            tiles,
+            solver: TileSolver::new(compatibility, weights),
+        })
+    }
+
+    /// Builds the model from the complete set of tiles for the given
+    /// terrains. See [`WangTile::complete_set`].
+    pub fn complete(terrains: &[&str]) -> Result<Self, WfcError> {
+        WangModel::new(WangTile::complete_set(terrains))
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.solver.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Sets the axes along which the output wraps around.
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.solver.wrap = wrap;
+        self
+    }
+
+    /// The tiles, indexed by the ids used in the output.
+    pub fn tiles(&self) -> &[WangTile] {
+        &self.tiles
+    }
+
+    /// Generates a grid `width` tiles wide and `height` tiles tall, as rows
+    /// of tile ids.
+    ///
+    /// The same seed always produces the same output for the same tiles.
+    pub fn generate(
+        &self,
+        width: usize,
+        height: usize,
+        seed: u64,
+    ) -> Result<Vec<Vec<usize>>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(width, height, &mut rng)
+    }
+
+    /// Like [`WangModel::generate`], but draws every random choice from
+    /// `rng`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> Result<Vec<Vec<usize>>, WfcError> {
+        self.solver.generate_rows(width, height, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::{Corners, WangModel, WangTile};
+    use crate::direction::Direction;
+    use crate::error::WfcError;
+    use crate::table::Wrap;
+    use crate::test_utils::assert_deterministic;
+
+    /// Checks that every pair of adjacent tiles agrees on the corners they
+    /// share.
+    fn assert_corners_agree(model: &WangModel, rows: &[Vec<usize>]) {
+        let corners = |id: usize| &model.tiles()[id].corners;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &id) in row.iter().enumerate() {
+                if let Some(&right) = row.get(x + 1) {
+                    assert_eq!(
+                        corners(id).side(Direction::Right),
+                        corners(right).side(Direction::Left)
+                    );
+                }
+                if let Some(below) = rows.get(y + 1) {
+                    assert_eq!(
+                        corners(id).side(Direction::Down),
+                        corners(below[x]).side(Direction::Up)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn complete_set() {
+        let tiles = WangTile::complete_set(&["grass", "water", "sand"]);
+        assert_eq!(tiles.len(), 81);
+        assert_eq!(tiles.iter().map(|tile| &tile.corners).unique().count(), 81);
+        assert_eq!(tiles[1].name, "grass-grass-grass-water");
+        assert_eq!(
+            tiles[1].corners,
+            Corners::new("grass", "grass", "grass", "water")
+        );
+    }
+
+    #[test]
+    fn generate() {
+        let model = WangModel::complete(&["grass", "water"]).unwrap();
+        for rows in assert_deterministic(|seed| model.generate(8, 6, seed)) {
+            assert_eq!(rows.len(), 6);
+            assert!(rows.iter().all(|row| row.len() == 8));
+            assert_corners_agree(&model, &rows);
+        }
+
+        let model = WangModel::complete(&["grass", "water", "sand"])
+            .unwrap()
+            .with_wrap(Wrap::Both);
+        let rows = model.generate(6, 6, 0).unwrap();
+        assert_corners_agree(&model, &rows);
+    }
+
+    #[test]
+    fn incomplete_set() {
+        // Without tiles mixing terrains, only one terrain can be used.
+        let tiles = vec![
+            WangTile::new("grass", Corners::new("g", "g", "g", "g")),
+            WangTile::new("water", Corners::new("w", "w", "w", "w")),
+        ];
+        let model = WangModel::new(tiles).unwrap();
+        for seed in 0..4 {
+            let rows = model.generate(5, 5, seed).unwrap();
+            assert!(rows.iter().flatten().all_equal());
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut tiles = WangTile::complete_set(&["a", "b"]);
+        tiles.push(WangTile::new("a-a-a-a", Corners::new("a", "a", "a", "a")));
+        assert!(matches!(
+            WangModel::new(tiles),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+
+        let tiles = vec![WangTile::new("a", Corners::new("a", "a", "a", "a")).with_weight(-1.0)];
+        assert!(matches!(
+            WangModel::new(tiles),
+            Err(WfcError::InvalidTileset { .. })
+        ));
+
+        assert_eq!(
+            WangModel::complete(&[]).unwrap().generate(2, 2, 0),
+            Err(WfcError::NoPatterns)
+        );
+    }
+}
@@ -1,257 +1,1519 @@
-use rustc_hash::FxHashMap as HashMap;
-use rustc_hash::FxHashSet as HashSet;
-
-use image;
-use itertools::iproduct;
 use itertools::Itertools;
-use rand::seq::IteratorRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rustc_hash::FxHashMap as HashMap;
 
+use crate::bitset::{BitSet, BitSets};
+use crate::compatibility::Compatibility;
+use crate::cube::{self, CubeMap, CubeSphere, Face};
 use crate::direction;
-use crate::direction::Direction;
-use crate::pattern;
+use crate::error::WfcError;
+use crate::heap::IndexedHeap;
+use crate::pattern::Pattern;
 use crate::table;
-use crate::Image;
+use crate::texture::{CellValue, Texture};
+use crate::topology::Topology;
 
-type CTable = HashMap<(usize, usize), u8>;
-type ETable<'p> = table::Table<Vec<&'p pattern::Pattern<'p>>>;
+/// The default amount of times the solver is allowed to backtrack before
+/// giving up on a generation.
+pub const DEFAULT_BACKTRACK_LIMIT: usize = 1000;
+
+/// The scale of the noise added to the entropy of each slot, so that ties
+/// between slots with the least entropy are broken at random.
+const ENTROPY_NOISE: f64 = 1e-6;
 
 /// Wave Function Collapse.
 ///
 /// It generates arbitrarily sized textures from a given set of patterns.
-pub struct Wfc<'p> {
+pub struct Wfc<'p, T> {
     /// The patterns.
-    patterns: Vec<&'p pattern::Pattern<'p>>,
+    patterns: Vec<&'p Pattern<T>>,
     /// The constraints table.
     ///
-    /// This is a `NxNx4` matrix, where `N` is the number of patterns.
-    /// A member of the matrix is true if `p1` overlaps `p2` in the given direction.
-    ctable: CTable,
+    /// The constraints table is a `NxNx4` matrix, where `N` is the number of
+    /// patterns. A member of the matrix is true if `p1` overlaps `p2` in the
+    /// given direction.
+    compatibility: Compatibility,
+    /// The maximum amount of times the solver may backtrack in a single
+    /// generation.
+    backtrack_limit: usize,
+    /// How much the frequency of a pattern affects how likely it is to be
+    /// chosen.
+    temperature: f64,
+    /// The axes along which the output wraps around.
+    wrap: table::Wrap,
 }
 
-impl<'p> Wfc<'p> {
-    pub fn new(patterns: Vec<&'p pattern::Pattern<'p>>) -> Self {
-        let ctable = Wfc::build_constraints(&patterns);
-        Wfc { patterns, ctable }
+impl<'p, T: CellValue> Wfc<'p, T> {
+    pub fn new(patterns: Vec<&'p Pattern<T>>) -> Self {
+        let compatibility = Wfc::build_constraints(&patterns);
+        Wfc::with_constraints(patterns, compatibility)
     }
 
-    pub fn build_constraints(patterns: &Vec<&'p pattern::Pattern<'p>>) -> CTable {
-        let directions = direction::Direction::all();
-        let mut ctable = HashMap::default();
-        for (p1, p2) in iproduct!(patterns.iter(), patterns.iter()) {
-            let mut row = 0u8;
-            for d in directions {
-                row = row | (u8::from(p1.overlaps(p2, &d)) << u8::from(d));
-            }
-            ctable.insert((p1.id, p2.id), row);
+    /// Creates the model of the patterns, given their constraints table.
+    fn with_constraints(patterns: Vec<&'p Pattern<T>>, compatibility: Compatibility) -> Self {
+        Wfc {
+            patterns,
+            compatibility,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+            temperature: 1.0,
+            wrap: table::Wrap::None,
+        }
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Sets the temperature used to weight the patterns.
+    ///
+    /// The weight of a pattern is its frequency raised to `1 / temperature`,
+    /// so temperatures above `1` flatten the weights and temperatures
+    /// below `1` sharpen them.
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Sets the axes along which the output wraps around.
+    ///
+    /// Constraints are propagated across the wrapped borders, so the output
+    /// tiles seamlessly along them.
+    pub fn with_wrap(mut self, wrap: table::Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Computes the weight of every pattern, indexed by the pattern id.
+    fn weights(&self) -> Vec<f64> {
+        let len = self.patterns.iter().map(|p| p.id + 1).max().unwrap_or(0);
+        let mut weights = vec![0.0; len];
+        for pattern in &self.patterns {
+            weights[pattern.id] = (pattern.frequency as f64).powf(self.temperature.recip());
         }
 
-        ctable
+        weights
+    }
+
+    pub fn build_constraints(patterns: &Vec<&'p Pattern<T>>) -> Compatibility {
+        Compatibility::overlapping(
+            patterns
+                .iter()
+                .map(|p| (p.id, *p))
+                .sorted_by_key(|&(id, _)| id),
+            &direction::Direction::all(),
+            |d| d.opposite(),
+            |pattern, d| pattern.get_side(&d),
+        )
     }
 
     /// Implements the CSP solver.
-    pub fn generate(&self, width: u32, height: u32) -> Image {
-        let buffer = image::ImageBuffer::new(width, height);
+    ///
+    /// The same seed always produces the same output for the same patterns,
+    /// regardless of the platform.
+    ///
+    /// Fails if the output is empty, if there are no patterns, or if the
+    /// solver runs into a contradiction it can't backtrack out of.
+    pub fn generate(&self, width: usize, height: usize, seed: u64) -> Result<Texture<T>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_with_rng(width, height, &mut rng)
+    }
+
+    /// Like [`Wfc::generate`], but draws every random choice from `rng`.
+    pub fn generate_with_rng<R: Rng + ?Sized>(
+        &self,
+        width: usize,
+        height: usize,
+        rng: &mut R,
+    ) -> Result<Texture<T>, WfcError> {
+        if !(self.temperature > 0.0 && self.temperature.is_finite()) {
+            return Err(WfcError::InvalidTemperature {
+                temperature: self.temperature,
+            });
+        }
+
+        let ids = solve(
+            &self.compatibility,
+            self.weights(),
+            (width, height),
+            self.wrap,
+            self.backtrack_limit,
+            rng,
+        )?;
+
+        let mut by_id = vec![None; self.compatibility.len()];
+        for pattern in &self.patterns {
+            by_id[pattern.id] = Some(pattern);
+        }
+
+        Ok(Texture::from_fn(width, height, |x, y| {
+            by_id[ids[(x, y)]].unwrap().cells[0]
+        }))
+    }
+
+    /// Generates the six faces of a cube, `size` cells wide, as a single
+    /// output whose patterns overlap across the edges of the cube.
+    ///
+    /// The faces turn against each other around some of the edges, so
+    /// every pattern needs all of its quarter turns to be part of the
+    /// model, which only a symmetry of `8` guarantees. Fails with
+    /// [`WfcError::MissingRotation`] otherwise. Each pixel is the center of
+    /// the pattern of its cell, which lines up exactly across the edges for
+    /// odd pattern sizes.
+    ///
+    /// Contradictions point at a cell of the [`CubeSphere`] by its index.
+    pub fn generate_cube(&self, size: usize, seed: u64) -> Result<CubeMap<T>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.generate_cube_with_rng(size, &mut rng)
+    }
+
+    /// Like [`Wfc::generate_cube`], but draws every random choice from
+    /// `rng`.
+    pub fn generate_cube_with_rng<R: Rng + ?Sized>(
+        &self,
+        size: usize,
+        rng: &mut R,
+    ) -> Result<CubeMap<T>, WfcError> {
+        if !(self.temperature > 0.0 && self.temperature.is_finite()) {
+            return Err(WfcError::InvalidTemperature {
+                temperature: self.temperature,
+            });
+        }
+
+        if size == 0 {
+            return Err(WfcError::ZeroOutputSize {
+                width: 0,
+                height: 0,
+            });
+        }
+
+        let mut by_id = vec![None; self.compatibility.len()];
+        let mut ids = HashMap::default();
+        for pattern in &self.patterns {
+            by_id[pattern.id] = Some(pattern);
+            ids.insert(&pattern.cells, pattern.id);
+        }
+        let rotated = by_id
+            .iter()
+            .map(|pattern| ids.get(&pattern.as_ref()?.rotate().cells).copied())
+            .collect_vec();
+        if let Some(pattern) = self
+            .patterns
+            .iter()
+            .find(|pattern| rotated[pattern.id].is_none())
+        {
+            return Err(WfcError::MissingRotation {
+                pattern: pattern.id,
+            });
+        }
+
+        let compatibility = cube::compatibility(&self.compatibility, &rotated);
+        let cells = CubeSphere::new(size);
+        let graph = Graph::new(&cells);
+        let ids = solve_graph(
+            &compatibility,
+            self.weights(),
+            &graph,
+            self.backtrack_limit,
+            rng,
+        )
+        .map_err(Failure::into_node_error)?;
+
+        let faces = Face::all()
+            .into_iter()
+            .map(|face| {
+                Texture::from_fn(size, size, |x, y| {
+                    let pattern = by_id[ids[cells.pos_to_idx(face, (x, y))]].unwrap();
+                    pattern[(pattern.size / 2, pattern.size / 2)]
+                })
+            })
+            .collect();
+
+        Ok(CubeMap::new(faces))
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<'p, T: CellValue + Send + Sync> Wfc<'p, T> {
+    /// Like [`Wfc::new`], but builds the constraints table in parallel.
+    pub fn par_new(patterns: Vec<&'p Pattern<T>>) -> Self {
+        let compatibility = Wfc::par_build_constraints(&patterns);
+        Wfc::with_constraints(patterns, compatibility)
+    }
+
+    /// Like [`Wfc::build_constraints`], but looks up the overlapping
+    /// patterns of each chunk of patterns in parallel.
+    pub fn par_build_constraints(patterns: &Vec<&'p Pattern<T>>) -> Compatibility {
+        Compatibility::par_overlapping(
+            patterns
+                .iter()
+                .map(|p| (p.id, *p))
+                .sorted_by_key(|&(id, _)| id),
+            &direction::Direction::all(),
+            |d| d.opposite(),
+            |pattern, d| pattern.get_side(&d),
+        )
+    }
+}
+
+/// Fills a `width`x`height` grid with patterns that satisfy the constraints
+/// table, returning the id of the pattern each cell collapsed to.
+///
+/// Only the patterns with a positive weight are placed. Rows of the returned
+/// table are columns of the output, which keeps the table's directions in
+/// line with the ones used to build the constraints.
+///
+/// Fails if the output is empty, if there are no patterns to place, or if
+/// the solver runs into a contradiction it can't backtrack out of.
+pub(crate) fn solve<R: Rng + ?Sized>(
+    compatibility: &Compatibility,
+    weights: Vec<f64>,
+    (width, height): (usize, usize),
+    wrap: table::Wrap,
+    backtrack_limit: usize,
+    rng: &mut R,
+) -> Result<table::Table<usize>, WfcError> {
+    if width == 0 || height == 0 {
+        return Err(WfcError::ZeroOutputSize { width, height });
+    }
+
+    let cells = table::Table::new(vec![(); width * height], height).with_wrap(wrap);
+    let graph = Graph::new(&cells);
+    let ids = solve_graph(compatibility, weights, &graph, backtrack_limit, rng)
+        .map_err(|failure| failure.into_error(|slot| cells.idx_to_pos(slot)))?;
+    Ok(table::Table::new(ids, height))
+}
+
+/// Fills the cells of any [`Topology`] with patterns that satisfy a
+/// constraints table, such as a grid with holes in it or the rooms of a
+/// dungeon.
+pub struct Solver {
+    /// The patterns that can be put on the other end of each edge label.
+    compatibility: Compatibility,
+    /// How likely each pattern is to be chosen, relative to the others.
+    weights: Vec<f64>,
+    /// The maximum amount of times the solver may backtrack in a single
+    /// generation.
+    backtrack_limit: usize,
+}
+
+impl Solver {
+    /// Creates a solver for the patterns of `compatibility`, which are
+    /// chosen according to their `weights`. Patterns without a positive
+    /// weight are never placed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't a weight for every pattern.
+    pub fn new(compatibility: Compatibility, weights: Vec<f64>) -> Self {
+        assert_eq!(weights.len(), compatibility.len());
+
+        Solver {
+            compatibility,
+            weights,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT,
+        }
+    }
+
+    /// Sets the maximum amount of times the solver may backtrack after
+    /// running into a contradiction.
+    pub fn with_backtrack_limit(mut self, backtrack_limit: usize) -> Self {
+        self.backtrack_limit = backtrack_limit;
+        self
+    }
+
+    /// Fills every cell of `topology`, returning the id of the pattern each
+    /// cell collapsed to.
+    ///
+    /// The same seed always produces the same output for the same
+    /// topology.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the topology doesn't have as many edge labels as the
+    /// constraints table has directions, or if one of its edges has no edge
+    /// leading back.
+    pub fn solve<T: Topology + ?Sized>(
+        &self,
+        topology: &T,
+        seed: u64,
+    ) -> Result<Vec<usize>, WfcError> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.solve_with_rng(topology, &mut rng)
+    }
+
+    /// Like [`Solver::solve`], but draws every random choice from `rng`.
+    pub fn solve_with_rng<T: Topology + ?Sized, R: Rng + ?Sized>(
+        &self,
+        topology: &T,
+        rng: &mut R,
+    ) -> Result<Vec<usize>, WfcError> {
+        assert_eq!(topology.directions(), self.compatibility.directions());
+
+        let graph = Graph::new(topology);
+        solve_graph(
+            &self.compatibility,
+            self.weights.clone(),
+            &graph,
+            self.backtrack_limit,
+            rng,
+        )
+        .map_err(Failure::into_node_error)
+    }
+}
+
+/// Fills every slot of `graph` with a pattern that satisfies the
+/// constraints table, returning the id of the pattern each slot collapsed
+/// to.
+///
+/// The directions of the graph are the ones of the constraints table.
+pub(crate) fn solve_graph<R: Rng + ?Sized>(
+    compatibility: &Compatibility,
+    weights: Vec<f64>,
+    graph: &Graph,
+    backtrack_limit: usize,
+    rng: &mut R,
+) -> Result<Vec<usize>, Failure> {
+    let weights = Weights::new(weights);
+    let mut possible = BitSet::new(weights.len());
+    for (id, &weight) in weights.weights.iter().enumerate() {
+        if weight > 0.0 {
+            possible.insert(id);
+        }
+    }
+
+    if possible.count() == 0 {
+        return Err(Failure::NoPatterns);
+    }
+
+    let lists = CompatibleLists::new(compatibility);
+    let mut solver = WfcI::new(&lists, &weights, graph, &possible, rng);
+
+    let mut backtracks = 0;
+    loop {
+        if let Err(slot) = solver.propagate() {
+            let observations = solver.observations;
+            if solver.decisions.is_empty() {
+                return Err(Failure::Contradiction { slot, observations });
+            }
+
+            if backtracks == backtrack_limit {
+                return Err(Failure::BacktrackLimitReached {
+                    limit: backtrack_limit,
+                    slot,
+                    observations,
+                });
+            }
+
+            backtracks += 1;
+            solver.backtrack();
+        } else if solver.observe(rng).is_none() {
+            break;
+        }
+    }
+
+    assert!(solver.slots.iter().all(|x| x.count == 1));
+    debug_assert!(solver.is_consistent());
+
+    Ok((0..solver.slots.len())
+        .map(|idx| solver.wave.iter(idx).next().unwrap())
+        .collect())
+}
+
+/// The ways in which [`solve_graph`] can fail, which each model turns into
+/// a [`WfcError`] that points at the failing slot in its own terms.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Failure {
+    /// None of the patterns has a positive weight.
+    NoPatterns,
+    /// The solver ran into a contradiction before making any observation.
+    Contradiction { slot: usize, observations: usize },
+    /// The solver ran into a contradiction after backtracking as many
+    /// times as it was allowed to.
+    BacktrackLimitReached {
+        limit: usize,
+        slot: usize,
+        observations: usize,
+    },
+}
+
+impl Failure {
+    /// Turns the failure into an error, using `cell` to find the position
+    /// of a slot in a two-dimensional output.
+    pub(crate) fn into_error(self, cell: impl Fn(usize) -> (usize, usize)) -> WfcError {
+        match self {
+            Failure::NoPatterns => WfcError::NoPatterns,
+            Failure::Contradiction { slot, observations } => WfcError::Contradiction {
+                cell: cell(slot),
+                observations,
+            },
+            Failure::BacktrackLimitReached {
+                limit,
+                slot,
+                observations,
+            } => WfcError::BacktrackLimitReached {
+                limit,
+                cell: cell(slot),
+                observations,
+            },
+        }
+    }
+
+    /// Turns the failure into an error, using `voxel` to find the position
+    /// of a slot in a voxel output.
+    pub(crate) fn into_voxel_error(
+        self,
+        voxel: impl Fn(usize) -> (usize, usize, usize),
+    ) -> WfcError {
+        match self {
+            Failure::NoPatterns => WfcError::NoPatterns,
+            Failure::Contradiction { slot, observations } => WfcError::VoxelContradiction {
+                voxel: voxel(slot),
+                observations,
+            },
+            Failure::BacktrackLimitReached {
+                limit,
+                slot,
+                observations,
+            } => WfcError::VoxelBacktrackLimitReached {
+                limit,
+                voxel: voxel(slot),
+                observations,
+            },
+        }
+    }
+
+    /// Turns the failure into an error that points at the failing slot by
+    /// its index.
+    pub(crate) fn into_node_error(self) -> WfcError {
+        match self {
+            Failure::NoPatterns => WfcError::NoPatterns,
+            Failure::Contradiction { slot, observations } => WfcError::NodeContradiction {
+                node: slot,
+                observations,
+            },
+            Failure::BacktrackLimitReached {
+                limit,
+                slot,
+                observations,
+            } => WfcError::NodeBacktrackLimitReached {
+                limit,
+                node: slot,
+                observations,
+            },
+        }
+    }
+}
+
+/// The slots of an output and the edges between them, laid out so that the
+/// solver can walk them quickly.
+pub(crate) struct Graph {
+    /// The amount of edge labels, which are the directions of the
+    /// constraints table.
+    directions: usize,
+    /// The label of the edge leading back, for each label.
+    opposites: Vec<usize>,
+    /// Where the edges of each slot start in `edges`, followed by the total
+    /// amount of edges.
+    starts: Vec<usize>,
+    /// The edges of every slot, one slot after the other.
+    edges: Vec<Edge>,
+}
+
+/// An edge leaving a slot.
+#[derive(Clone, Copy)]
+struct Edge {
+    /// The label of the edge, which is a direction of the constraints table.
+    direction: usize,
+    /// The slot the edge leads to.
+    neighbor: usize,
+    /// The index of the edge leading back, in [`Graph::edges`].
+    back: usize,
+}
+
+impl Graph {
+    /// Lays out the cells of a topology.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a cell isn't a neighbor of its neighbors, with the opposite
+    /// label.
+    pub(crate) fn new<T: Topology + ?Sized>(topology: &T) -> Self {
+        let directions = topology.directions();
+        let opposites = (0..directions).map(|d| topology.opposite(d)).collect_vec();
+
+        let mut starts = Vec::with_capacity(topology.len() + 1);
+        let mut neighbors = Vec::new();
+        for idx in 0..topology.len() {
+            starts.push(neighbors.len());
+            neighbors.extend(topology.neighbors(idx));
+        }
+        starts.push(neighbors.len());
+
+        let mut edges = Vec::with_capacity(neighbors.len());
+        for idx in 0..topology.len() {
+            for &(direction, neighbor) in &neighbors[starts[idx]..starts[idx + 1]] {
+                let back = (starts[neighbor]..starts[neighbor + 1])
+                    .find(|&back| neighbors[back] == (opposites[direction], idx))
+                    .expect("every edge should have an edge leading back");
+                edges.push(Edge {
+                    direction,
+                    neighbor,
+                    back,
+                });
+            }
+        }
 
-        let mut entropy = Vec::with_capacity(width as usize * height as usize);
-        for _ in 0..width * height {
-            entropy.push(self.patterns.clone());
+        Graph {
+            directions,
+            opposites,
+            starts,
+            edges,
         }
-        let etable = table::Table::new(entropy, width as usize);
-        let mut solver = WfcI::new(&self.ctable, etable, buffer);
+    }
+
+    /// The amount of slots.
+    pub(crate) fn len(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    /// The edges leaving the slot at `idx`.
+    fn edges(&self, idx: usize) -> &[Edge] {
+        &self.edges[self.starts[idx]..self.starts[idx + 1]]
+    }
+}
+
+/// The weight of every pattern, indexed by the pattern id.
+struct Weights {
+    weights: Vec<f64>,
+    /// The weight of every pattern multiplied by its logarithm, which is
+    /// needed to compute entropies.
+    weight_log_weights: Vec<f64>,
+}
+
+impl Weights {
+    fn new(weights: Vec<f64>) -> Self {
+        let weight_log_weights = weights
+            .iter()
+            .map(|&w| if w > 0.0 { w * w.ln() } else { 0.0 })
+            .collect();
 
-        while let Some(observed_idx) = solver.observe() {
-            solver.propagate(observed_idx);
+        Weights {
+            weights,
+            weight_log_weights,
         }
+    }
 
-        assert!(solver.etable.iter().all(|x| x.len() == 1));
+    fn len(&self) -> usize {
+        self.weights.len()
+    }
+}
+
+/// The patterns compatible with each pattern in each direction of a
+/// constraints table, one list after the other.
+struct CompatibleLists<'a> {
+    compatibility: &'a Compatibility,
+    directions: usize,
+    /// Where the list of each pattern and direction starts in `ids`,
+    /// followed by the total amount of ids.
+    starts: Vec<usize>,
+    ids: Vec<usize>,
+}
 
-        for i in 0..height {
-            for j in 0..width {
-                let idx = i * width + j;
-                let pattern = solver.etable[idx as usize][0];
-                let color = pattern.pixels[0];
-                solver.buffer.put_pixel(i, j, image::Rgb(color.to_slice()));
+impl<'a> CompatibleLists<'a> {
+    fn new(compatibility: &'a Compatibility) -> Self {
+        let directions = compatibility.directions();
+        let mut starts = Vec::with_capacity(compatibility.len() * directions + 1);
+        let mut ids = Vec::new();
+        for p1 in 0..compatibility.len() {
+            for d in 0..directions {
+                starts.push(ids.len());
+                ids.extend(compatibility.compatible(p1, d));
             }
         }
+        starts.push(ids.len());
 
-        solver.buffer
+        CompatibleLists {
+            compatibility,
+            directions,
+            starts,
+            ids,
+        }
+    }
+
+    /// The patterns that can be put next to `p1` in the given direction.
+    fn get(&self, p1: usize, direction: usize) -> &[usize] {
+        let list = p1 * self.directions + direction;
+        &self.ids[self.starts[list]..self.starts[list + 1]]
     }
 }
 
+/// A slot of the output, counting the patterns it can still collapse to.
+///
+/// The amount of possible patterns and the sums its entropy is made of are
+/// kept up to date as patterns are removed, so that the entropy doesn't
+/// have to be recomputed from every pattern on every observation.
+#[derive(Debug, Clone)]
+struct Slot {
+    /// The amount of possible patterns.
+    count: usize,
+    /// The sum of the weights of the possible patterns.
+    sum_of_weights: f64,
+    /// The sum of `w * ln(w)` over the weights of the possible patterns.
+    sum_of_weight_log_weights: f64,
+}
+
+impl Slot {
+    fn new(possible: &BitSet, weights: &Weights) -> Self {
+        Slot {
+            count: possible.count(),
+            sum_of_weights: possible.iter().map(|id| weights.weights[id]).sum(),
+            sum_of_weight_log_weights: possible
+                .iter()
+                .map(|id| weights.weight_log_weights[id])
+                .sum(),
+        }
+    }
+
+    /// Accounts for the pattern with the given id no longer being possible.
+    fn remove(&mut self, id: usize, weights: &Weights) {
+        self.count -= 1;
+        self.sum_of_weights -= weights.weights[id];
+        self.sum_of_weight_log_weights -= weights.weight_log_weights[id];
+    }
+
+    /// Accounts for the pattern with the given id being possible again.
+    fn insert(&mut self, id: usize, weights: &Weights) {
+        self.count += 1;
+        self.sum_of_weights += weights.weights[id];
+        self.sum_of_weight_log_weights += weights.weight_log_weights[id];
+    }
+
+    /// The Shannon entropy of the slot, weighting each possible pattern.
+    fn entropy(&self) -> f64 {
+        self.sum_of_weights.ln() - self.sum_of_weight_log_weights / self.sum_of_weights
+    }
+}
+
+/// An observation made by the solver.
+///
+/// Observations are the only choices the solver makes, so they are the
+/// points it can go back to when it runs into a contradiction.
+struct Decision {
+    /// The index of the observed slot.
+    idx: usize,
+    /// The id of the pattern the slot collapsed to.
+    pattern: usize,
+    /// The length of the trail before the observation was made.
+    trail_len: usize,
+}
+
 /// The internal representation of the WFC solver.
 ///
 /// This is a wrapper around the `Wfc` struct, which contains the constraints
 /// table and the input patterns.
-pub struct WfcI<'p> {
-    /// The constraints table.
-    ///
-    /// This is a `NxNx4` matrix, where `N` is the number of patterns.
-    /// A member of the matrix is true if `p1` overlaps `p2` in the given direction.
-    ctable: &'p CTable,
-    /// The entropy table.
+///
+/// Propagation follows the AC-4 algorithm: every slot keeps, for each of its
+/// patterns and each direction, the amount of patterns in the neighboring
+/// slot that support it. A pattern is banned once any of its counters drops
+/// to zero.
+struct WfcI<'a> {
+    /// The compatible patterns of each pattern in each direction.
+    compatibility: &'a Compatibility,
+    /// The weight of every pattern.
+    weights: &'a Weights,
+    /// The slots of the output and their neighbors.
+    graph: &'a Graph,
+    /// The patterns each slot can collapse to.
+    wave: BitSets,
+    /// The amount of patterns each slot can collapse to, and its entropy.
+    slots: Vec<Slot>,
+    /// The amount of supporting patterns across each edge, indexed by
+    /// `edge * P + pattern`, where the edge leads from the supported slot to
+    /// the supporting one, so that the counters updated together when
+    /// propagating a ban are next to each other.
+    supports: Vec<u32>,
+    /// The bans that still have to be propagated.
+    stack: Vec<(usize, usize)>,
+    /// The first slot left without possibilities since the last propagation.
+    contradiction: Option<usize>,
+    /// The same patterns as `compatibility`, as lists that are quicker to
+    /// walk than the rows of the table.
+    lists: &'a CompatibleLists<'a>,
+    /// The slots that haven't collapsed yet, ordered by their entropy.
     ///
-    /// This is a `NxMxP` matrix, where `N` & `M` are the width & the height
-    /// of the output image, and `P` is the number of patterns.
-    etable: ETable<'p>,
-    /// The output image.
-    buffer: image::ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    /// Slots only move in the heap before an observation, so that a slot
+    /// losing many patterns during a propagation moves once.
+    heap: IndexedHeap,
+    /// The slots whose entropy changed since the heap was last updated.
+    changed: Vec<usize>,
+    /// Whether each slot is in `changed`.
+    is_changed: Vec<bool>,
+    /// The noise added to the entropy of each slot.
+    noise: Vec<f64>,
+    /// Every pattern banned from a slot, in the order they were banned.
+    trail: Vec<(usize, usize)>,
+    /// The observations that led to the current state of the slots.
+    decisions: Vec<Decision>,
+    /// The amount of observations made so far, including undone ones.
+    observations: usize,
 }
 
-impl<'p> WfcI<'p> {
-    fn new(
-        ctable: &'p CTable,
-        etable: ETable<'p>,
-        buffer: image::ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+impl<'a> WfcI<'a> {
+    /// Creates the solver with every slot able to collapse to any of the
+    /// `possible` patterns, banning every pattern that has no compatible
+    /// pattern in the direction of one of its slot's neighbors.
+    fn new<R: Rng + ?Sized>(
+        lists: &'a CompatibleLists<'a>,
+        weights: &'a Weights,
+        graph: &'a Graph,
+        possible: &BitSet,
+        rng: &mut R,
     ) -> Self {
-        WfcI {
-            ctable,
-            etable,
-            buffer,
+        // A pattern is initially supported by every possible pattern that
+        // lists it as compatible in the opposite direction. Patterns that
+        // can't be placed support nothing, or they'd keep alive patterns
+        // that can only be put next to them.
+        let compatibility = lists.compatibility;
+        let len = compatibility.len();
+        let directions = graph.directions;
+        let mut initial = vec![0u32; directions * len];
+        for p1 in possible.iter() {
+            for d in 0..directions {
+                for &id in lists.get(p1, d) {
+                    initial[graph.opposites[d] * len + id] += 1;
+                }
+            }
         }
-    }
 
-    fn observe(&mut self) -> Option<usize> {
-        let min = self
-            .etable
-            .iter()
-            .map(|x| x.len())
-            .filter(|&x| x > 1)
-            .min()?;
+        let mut supports = Vec::with_capacity(graph.edges.len() * len);
+        for edge in &graph.edges {
+            supports.extend_from_slice(&initial[edge.direction * len..(edge.direction + 1) * len]);
+        }
 
-        let least_entropy = self
-            .etable
-            .iter()
-            .enumerate()
-            .filter(|(_, x)| x.len() == min);
+        // The possible patterns nothing supports along each label.
+        let unsupported = (0..directions)
+            .map(|d| {
+                possible
+                    .iter()
+                    .filter(|&id| initial[d * len + id] == 0)
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        let wave = BitSets::repeat(possible, graph.len());
+        let slots = vec![Slot::new(possible, weights); graph.len()];
+        let noise = (0..slots.len())
+            .map(|_| ENTROPY_NOISE * rng.gen::<f64>())
+            .collect_vec();
+        let mut heap = IndexedHeap::new(slots.len());
+        for (idx, slot) in slots.iter().enumerate() {
+            if slot.count > 1 {
+                heap.push(idx, slot.entropy() + noise[idx]);
+            }
+        }
+
+        let mut solver = WfcI {
+            compatibility,
+            lists,
+            weights,
+            graph,
+            supports,
+            stack: Vec::new(),
+            contradiction: None,
+            heap,
+            changed: Vec::new(),
+            is_changed: vec![false; slots.len()],
+            wave,
+            slots,
+            noise,
+            trail: Vec::new(),
+            decisions: Vec::new(),
+            observations: 0,
+        };
+
+        for idx in 0..solver.slots.len() {
+            for edge in graph.edges(idx) {
+                for &id in &unsupported[edge.direction] {
+                    if solver.wave.contains(idx, id) {
+                        solver.ban(idx, id);
+                    }
+                }
+            }
+        }
+
+        solver
+    }
 
-        let mut rng = rand::thread_rng();
-        let (idx, slot) = least_entropy.choose(&mut rng)?;
-        let observed = slot.iter().choose(&mut rng)?;
+    /// Collapses the slot with the least entropy.
+    ///
+    /// Returns `None` if every slot has already collapsed.
+    fn observe<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Option<usize> {
+        self.update_heap();
+        let idx = self.heap.peek()?;
+        let mut threshold = rng.gen::<f64>() * self.slots[idx].sum_of_weights;
+        let mut possible = self.wave.iter(idx);
+        let mut observed = possible.next()?;
+        for id in possible {
+            threshold -= self.weights.weights[observed];
+            if threshold < 0.0 {
+                break;
+            }
+            observed = id;
+        }
 
-        self.etable[idx] = vec![*observed];
+        self.collapse(idx, observed);
+        self.observations += 1;
 
         Some(idx)
     }
 
-    fn propagate(&mut self, start_idx: usize) {
-        // The upper bound on the stack size is the size of the
-        // output image, since at most we can have all the pixels
-        // yet to be propagated to on the stack.
-        let mut stack = Vec::with_capacity(self.etable.len());
-        // We also keep a HashSet of the indices that we already have on the stack.
-        let mut stack_set = HashSet::default();
-
-        // Start by pushing the observed pattern onto the stack.
-        stack.push(start_idx);
-        stack_set.insert(start_idx);
-
-        while let Some(current_idx) = stack.pop() {
-            stack_set.remove(&current_idx);
-            let (x, y) = self.etable.idx_to_pos(current_idx);
-
-            // Get the neighbors of the current pattern.
-
-            for (nx, ny) in self.etable.get_neighbors((x, y)) {
-                let neighbor_possibilities = self.etable.get((nx, ny));
-
-                let mut remaining_set = HashSet::default();
-                let direction = Direction::from_neighbors((x, y), (nx, ny));
-                for possibility in self.etable.get((x, y)) {
-                    let remaining = neighbor_possibilities
-                        .iter()
-                        .filter(|&p| {
-                            // Check if `p` is compatible with the observed pattern
-                            // in direction `direction`. It's okay to unwrap since we
-                            // have to assume the table is populated correctly.
-                            let constraints = self.ctable.get(&(possibility.id, p.id)).unwrap();
-
-                            // Check if the constraints are satisfied.
-                            (constraints >> u8::from(direction) & 1) != 0
-                        })
-                        .collect_vec();
-
-                    // Add the possible slots that this possibility enables.
-                    remaining_set.extend(remaining);
-                }
+    /// Collapses the slot at `idx` to the pattern `observed`, remembering
+    /// the decision so that it can be undone later.
+    fn collapse(&mut self, idx: usize, observed: usize) {
+        self.decisions.push(Decision {
+            idx,
+            pattern: observed,
+            trail_len: self.trail.len(),
+        });
+
+        let banned = self
+            .wave
+            .iter(idx)
+            .filter(|&id| id != observed)
+            .collect_vec();
+        for id in banned {
+            self.ban(idx, id);
+        }
+    }
+
+    /// Removes the pattern `id` from the slot at `idx`.
+    ///
+    /// The ban is only propagated to the neighbors on the next call to
+    /// `propagate`.
+    fn ban(&mut self, idx: usize, id: usize) {
+        if self.wave.remove(idx, id) {
+            self.slots[idx].remove(id, self.weights);
+        }
+        self.changed(idx);
+        self.trail.push((idx, id));
+        self.stack.push((idx, id));
+
+        if self.slots[idx].count == 0 && self.contradiction.is_none() {
+            self.contradiction = Some(idx);
+        }
+    }
+
+    /// Remembers that the entropy of the slot at `idx` changed, so that it
+    /// moves in the heap before the next observation.
+    fn changed(&mut self, idx: usize) {
+        if !self.is_changed[idx] {
+            self.is_changed[idx] = true;
+            self.changed.push(idx);
+        }
+    }
+
+    /// Moves every slot whose entropy changed to its place in the heap,
+    /// dropping the ones that collapsed.
+    fn update_heap(&mut self) {
+        for idx in self.changed.drain(..) {
+            self.is_changed[idx] = false;
+            let slot = &self.slots[idx];
+            if slot.count > 1 {
+                self.heap.push(idx, slot.entropy() + self.noise[idx]);
+            } else {
+                self.heap.remove(idx);
+            }
+        }
+    }
+
+    /// Undoes the last observation and bans the pattern it chose.
+    ///
+    /// Returns the index of the observed slot, or `None` if there are no
+    /// observations left to undo.
+    fn backtrack(&mut self) -> Option<usize> {
+        let decision = self.decisions.pop()?;
+
+        let len = self.compatibility.len();
+        while self.trail.len() > decision.trail_len {
+            let (idx, id) = self.trail.pop()?;
+            if self.wave.insert(idx, id) {
+                self.slots[idx].insert(id, self.weights);
+            }
+            self.changed(idx);
 
-                // If there are no possible patterns after propagation,
-                // we have a contradiction.
-                if remaining_set.is_empty() {
-                    panic!("Contradiction");
+            // Give back the support the pattern lent to its neighbors.
+            for edge in self.graph.edges(idx) {
+                let supports = &mut self.supports[edge.back * len..];
+                for &compatible in self.lists.get(id, edge.direction) {
+                    supports[compatible] += 1;
                 }
+            }
+        }
+
+        self.ban(decision.idx, decision.pattern);
+
+        Some(decision.idx)
+    }
 
-                // If there was a change in possibilities we propagate that
-                // position as well.
-                //
-                // This is needed because if we remove possibilities from a
-                // slot S we might end up in a situation where a neighbor of
-                // S gets observed with a pattern that has no overlap with
-                // any of the possible patterns in S.
-                if neighbor_possibilities.len() != remaining_set.len() {
-                    let idx = nx * self.etable.width() + ny;
-
-                    // If the neighbor is not already on the stack, we push it.
-                    if stack_set.insert(idx) {
-                        stack.push(idx)
+    /// Propagates every pending ban to the neighbors of the banned slots.
+    ///
+    /// Propagation always runs to completion, so that every ban in the trail
+    /// has been accounted for in the support counters when it's undone.
+    ///
+    /// Returns the index of the first slot left without possibilities, if
+    /// propagation ran into a contradiction.
+    fn propagate(&mut self) -> Result<(), usize> {
+        let lists = self.lists;
+        let graph = self.graph;
+        let len = self.compatibility.len();
+        while let Some((idx, id)) = self.stack.pop() {
+            for &Edge {
+                direction: d,
+                neighbor,
+                back,
+            } in graph.edges(idx)
+            {
+                // The banned pattern no longer supports its compatible
+                // patterns in the neighbor, which get banned once nothing
+                // supports them from this side.
+                let base = back * len;
+                for &compatible in lists.get(id, d) {
+                    let support = &mut self.supports[base + compatible];
+                    *support -= 1;
+                    if *support == 0 && self.wave.contains(neighbor, compatible) {
+                        self.ban(neighbor, compatible);
                     }
                 }
-
-                // Collapse the neighboring slot.
-                self.etable[(nx, ny)] = remaining_set.into_iter().collect();
             }
         }
+
+        match self.contradiction.take() {
+            Some(idx) => Err(idx),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that every pattern left in a slot is allowed by the patterns
+    /// left in each of its neighbors.
+    fn is_consistent(&self) -> bool {
+        (0..self.slots.len()).all(|idx| {
+            self.graph.edges(idx).iter().all(|edge| {
+                let allowed = self.compatibility.allowed(
+                    self.wave.iter(edge.neighbor),
+                    self.graph.opposites[edge.direction],
+                );
+                self.wave.iter(idx).all(|id| allowed.contains(id))
+            })
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use rustc_hash::FxHashMap as HashMap;
-
-    use image::{Rgb, RgbImage};
     use itertools::Itertools;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
 
-    use crate::test_utils::p;
+    use super::{Slot, Weights};
+    use crate::compatibility::Compatibility;
+    use crate::cube::{CubeSphere, Face};
+    use crate::direction::Direction;
+    use crate::error::WfcError;
+    use crate::table::{Table, Wrap};
+    use crate::test_utils::{c, img, p};
+    use crate::texture::Texture;
+    use crate::topology::{GraphTopology, Topology};
 
     #[test]
     fn build_constraints() {
         // [0, 1, 2]
         // [1, 2, 3]
         // [2, 3, 4]
-        let mut texture = RgbImage::new(4, 4);
-        for x in 0..4 {
-            for y in 0..4 {
-                texture.put_pixel(x, y, Rgb([(x + y) as u8, 0, 0]));
-            }
-        }
+        let texture = Texture::from_fn(4, 4, |x, y| c((x + y) as u8));
 
-        let patterns = vec![p(0, 2, &texture, (0, 0)), p(1, 2, &texture, (1, 0))];
+        let patterns = [p(0, 2, &texture, (0, 0)), p(1, 2, &texture, (1, 0))];
 
-        let mut expected = HashMap::default();
-        expected.insert((0, 0), 0b0000);
-        expected.insert((0, 1), 0b0110);
-        expected.insert((1, 0), 0b1001);
-        expected.insert((1, 1), 0b0000);
-        let actual = super::Wfc::new(patterns.iter().collect_vec()).ctable;
+        let expected = compatibility(
+            2,
+            &[
+                (0, 0, 0b0000),
+                (0, 1, 0b0110),
+                (1, 0, 0b1001),
+                (1, 1, 0b0000),
+            ],
+        );
+        let actual = super::Wfc::build_constraints(&patterns.iter().collect_vec());
         assert_eq!(expected, actual);
 
         // [0, 1, 2]
         // [1, 2, 3]
         // [2, 3, 4]
-        let mut texture = RgbImage::new(4, 4);
-        for x in 0..4 {
-            for y in 0..4 {
-                texture.put_pixel(x, y, Rgb([(x + y) as u8, 0, 0]));
+        let texture = Texture::from_fn(4, 4, |x, y| c((x + y) as u8));
+
+        let patterns = [p(0, 3, &texture, (0, 0)), p(1, 3, &texture, (1, 0))];
+
+        let expected = compatibility(
+            2,
+            &[
+                (0, 0, 0b0000),
+                (0, 1, 0b0110),
+                (1, 0, 0b1001),
+                (1, 1, 0b0000),
+            ],
+        );
+        let actual = super::Wfc::build_constraints(&patterns.iter().collect_vec());
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn build_constraints_matches_overlaps() {
+        // [0, 1, 0, 1]
+        // [1, 0, 1, 0]
+        // [0, 0, 1, 1]
+        // [1, 1, 0, 0]
+        let texture = Texture::from_fn(4, 4, |x, y| {
+            let value = if y < 2 { x + y } else { x / 2 + y };
+            c((value % 2) as u8)
+        });
+
+        for size in 2..4 {
+            let patterns = crate::augment(crate::get_patterns(&texture, size, true), 8).unwrap();
+            let patterns = patterns.iter().collect_vec();
+            let actual = super::Wfc::build_constraints(&patterns);
+            for (p1, p2) in patterns.iter().cartesian_product(&patterns) {
+                for d in Direction::all() {
+                    assert_eq!(actual.contains(p1.id, p2.id, d), p1.overlaps(p2, &d));
+                }
             }
         }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn par_build_constraints() {
+        // Enough patterns to be split across several chunks.
+        let texture = Texture::from_fn(12, 12, |x, y| c((x * y % 5) as u8));
+        let patterns = crate::get_patterns(&texture, 3, true);
+        assert_eq!(crate::par_get_patterns(&texture, 3, true), patterns);
 
-        let patterns = vec![p(0, 3, &texture, (0, 0)), p(1, 3, &texture, (1, 0))];
+        let patterns = crate::augment(patterns, 8).unwrap();
+        let patterns = patterns.iter().collect_vec();
+        assert!(patterns.len() > crate::compatibility::OVERLAPPING_CHUNK_SIZE);
+        assert_eq!(
+            super::Wfc::par_build_constraints(&patterns),
+            super::Wfc::build_constraints(&patterns)
+        );
+    }
 
-        let mut expected = HashMap::default();
-        expected.insert((0, 0), 0b0000);
-        expected.insert((0, 1), 0b0110);
-        expected.insert((1, 0), 0b1001);
-        expected.insert((1, 1), 0b0000);
-        let actual = super::Wfc::new(patterns.iter().collect_vec()).ctable;
-        assert_eq!(expected, actual);
+    /// Builds a compatibility table from rows of the form `(p1, p2, bits)`,
+    /// where bit `d` is set if `p2` can be put next to `p1` in direction `d`.
+    fn compatibility(len: usize, rows: &[(usize, usize, u8)]) -> Compatibility {
+        let mut compatibility = Compatibility::new(len);
+        for &(p1, p2, bits) in rows {
+            for d in Direction::all() {
+                if bits >> u8::from(d) & 1 != 0 {
+                    compatibility.insert(p1, p2, d);
+                }
+            }
+        }
+
+        compatibility
+    }
+
+    /// Patterns `0` and `1` can only be put next to each other along the
+    /// `y` axis, while pattern `2` can only be put next to itself.
+    fn alternating(len: usize) -> Compatibility {
+        let mut rows = vec![(0, 1, 0b1010), (1, 0, 0b1010)];
+        if len > 2 {
+            rows.push((2, 2, 0b1010));
+        }
+
+        compatibility(len, &rows)
+    }
+
+    #[test]
+    fn backtrack() {
+        let compatibility = alternating(3);
+
+        // A ring of 3 slots along the `y` axis.
+        let weights = Weights::new(vec![1.0; 3]);
+        let cells = Table::new(vec![(); 3], 3).with_wrap(Wrap::Vertical);
+        let graph = super::Graph::new(&cells);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let possible = (0..3).collect();
+        let lists = super::CompatibleLists::new(&compatibility);
+        let mut solver = super::WfcI::new(&lists, &weights, &graph, &possible, &mut rng);
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!(solver.slots.iter().all(|x| x.count == 3));
+
+        // Alternating patterns can't fill an odd ring.
+        solver.collapse(0, 0);
+        assert!(solver.propagate().is_err());
+
+        // Undoing the bans restores the cached entropies.
+        assert_eq!(solver.backtrack(), Some(0));
+        assert_eq!(solver.slots[0].count, 2);
+        assert_eq!(solver.slots[1].count, 3);
+        assert!((solver.slots[1].entropy() - 3f64.ln()).abs() < 1e-9);
+
+        // The only way left to fill the ring is with the last pattern.
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!((0..3).all(|idx| solver.wave.iter(idx).collect_vec() == vec![2]));
+        assert!(solver.decisions.is_empty());
+
+        // There's nothing left to undo.
+        solver.ban(0, 2);
+        assert_eq!(solver.propagate(), Err(0));
+        assert_eq!(solver.backtrack(), None);
+    }
+
+    #[test]
+    fn consistency() {
+        // A triangle with a second edge between slots `0` and `1`, whose
+        // label is its own opposite, where neighbors must differ.
+        let mut compatibility = Compatibility::with_directions(3, 3);
+        for (p1, p2) in (0..3).cartesian_product(0..3) {
+            for d in 0..3usize {
+                if p1 != p2 {
+                    compatibility.insert(p1, p2, d);
+                }
+            }
+        }
+        let adjacency = vec![
+            vec![(0, 1), (1, 2), (2, 1)],
+            vec![(1, 0), (0, 2), (2, 0)],
+            vec![(1, 1), (0, 0)],
+        ];
+        let triangle = GraphTopology::new(vec![1, 0, 2], adjacency).unwrap();
+
+        let weights = Weights::new(vec![1.0; 3]);
+        let graph = super::Graph::new(&triangle);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let possible = (0..3).collect();
+        let lists = super::CompatibleLists::new(&compatibility);
+        let mut solver = super::WfcI::new(&lists, &weights, &graph, &possible, &mut rng);
+        let counts = |solver: &super::WfcI| solver.slots.iter().map(|x| x.count).collect_vec();
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!(solver.is_consistent());
+        assert_eq!(counts(&solver), vec![3, 3, 3]);
+
+        solver.collapse(0, 0);
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!(solver.is_consistent());
+        assert_eq!(counts(&solver), vec![1, 2, 2]);
+
+        solver.ban(1, 1);
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!(solver.is_consistent());
+        assert_eq!(counts(&solver), vec![1, 1, 1]);
+
+        // Undoing the observation gives back the support of every pattern
+        // it banned, across both edges between slots `0` and `1`.
+        assert_eq!(solver.backtrack(), Some(0));
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!(solver.is_consistent());
+        assert_eq!(counts(&solver), vec![2, 3, 3]);
+
+        solver.collapse(1, 1);
+        assert_eq!(solver.propagate(), Ok(()));
+        assert!(solver.is_consistent());
+        assert_eq!(counts(&solver), vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn zero_weights() {
+        // Pattern `0` can only be put next to pattern `1`, which is never
+        // placed, so two slots can't be filled.
+        let mut compatibility = Compatibility::with_directions(2, 1);
+        compatibility.insert(0, 1, 0usize);
+        compatibility.insert(1, 0, 0usize);
+        let pair = GraphTopology::new(vec![0], vec![vec![(0, 1)], vec![(0, 0)]]).unwrap();
+
+        let solver = super::Solver::new(compatibility, vec![1.0, 0.0]);
+        assert_eq!(
+            solver.solve(&pair, 0),
+            Err(WfcError::NodeContradiction {
+                node: 0,
+                observations: 0
+            })
+        );
+    }
+
+    #[test]
+    fn generate_errors() {
+        // [0, 1]
+        // [2, 3]
+        let texture = img(2);
+        let patterns = [p(0, 2, &texture, (0, 0)), p(1, 2, &texture, (0, 1))];
+        let patterns = patterns.iter().collect_vec();
+
+        let solver = super::Wfc::<u8>::new(vec![]);
+        assert_eq!(solver.generate(2, 2, 0), Err(WfcError::NoPatterns));
+
+        let solver = super::Wfc::new(patterns.clone());
+        assert_eq!(
+            solver.generate(0, 2, 0),
+            Err(WfcError::ZeroOutputSize {
+                width: 0,
+                height: 2
+            })
+        );
+
+        // No pattern can be put next to any other pattern, which is found
+        // out before making any observation.
+        let solver = super::Wfc {
+            compatibility: Compatibility::new(2),
+            patterns: patterns.clone(),
+            backtrack_limit: 1,
+            temperature: 1.0,
+            wrap: Wrap::None,
+        };
+        assert!(matches!(
+            solver.generate(1, 2, 0),
+            Err(WfcError::Contradiction {
+                observations: 0,
+                ..
+            })
+        ));
+
+        // Alternating patterns can't fill an odd ring, but that's only found
+        // out after observing a slot.
+        let solver = super::Wfc {
+            compatibility: alternating(2),
+            patterns,
+            backtrack_limit: 1,
+            temperature: 1.0,
+            wrap: Wrap::Vertical,
+        };
+        assert!(matches!(
+            solver.generate(1, 3, 0),
+            Err(WfcError::Contradiction {
+                observations: 1,
+                ..
+            })
+        ));
+
+        let solver = solver.with_backtrack_limit(0);
+        assert!(matches!(
+            solver.generate(1, 3, 0),
+            Err(WfcError::BacktrackLimitReached {
+                limit: 0,
+                observations: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn generate_is_deterministic() {
+        // [0, 1, 2, 3]
+        // [4, 5, 6, 7]
+        // [8, 9, 10, 11]
+        // [12, 13, 14, 15]
+        let texture = img(4);
+        let patterns = crate::get_patterns(&texture, 2, true);
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+
+        for seed in 0..8 {
+            let expected = solver.generate(8, 6, seed).unwrap();
+            let actual = solver.generate(8, 6, seed).unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn generate_sparse() {
+        let texture = img(4);
+        let patterns = crate::get_patterns(&texture, 2, true);
+        let dense = super::Wfc::new(patterns.iter().collect_vec());
+
+        let len = dense.compatibility.len();
+        let mut compatibility = Compatibility::sparse(len, 4);
+        for (p1, d) in (0..len).cartesian_product(Direction::all()) {
+            for p2 in dense.compatibility.compatible(p1, d) {
+                compatibility.insert(p1, p2, d);
+            }
+        }
+        let sparse = super::Wfc {
+            compatibility,
+            patterns: patterns.iter().collect_vec(),
+            backtrack_limit: super::DEFAULT_BACKTRACK_LIMIT,
+            temperature: 1.0,
+            wrap: Wrap::None,
+        };
+
+        for seed in 0..4 {
+            assert_eq!(dense.generate(8, 6, seed), sparse.generate(8, 6, seed));
+        }
+    }
+
+    #[test]
+    fn entropy() {
+        let weights = Weights::new(vec![1.0, 1.0]);
+        let mut slot = Slot::new(&(0..2).collect(), &weights);
+        assert_eq!(slot.count, 2);
+        assert!((slot.entropy() - 2f64.ln()).abs() < 1e-9);
+
+        slot.remove(1, &weights);
+        assert_eq!(slot.count, 1);
+        assert!(slot.entropy().abs() < 1e-9);
+
+        // A slot dominated by one pattern is more predictable.
+        let weights = Weights::new(vec![9.0, 1.0]);
+        let slot = Slot::new(&(0..2).collect(), &weights);
+        let expected = -(0.9 * 0.9f64.ln() + 0.1 * 0.1f64.ln());
+        assert!((slot.entropy() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn temperature() {
+        // [0, 1]
+        // [2, 3]
+        let texture = img(2);
+        let mut patterns = [p(0, 2, &texture, (0, 0)), p(1, 2, &texture, (0, 1))];
+        patterns[0].frequency = 4;
+
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+        assert_eq!(solver.weights(), vec![4.0, 1.0]);
+
+        let solver = solver.with_temperature(2.0);
+        assert_eq!(solver.weights(), vec![2.0, 1.0]);
+
+        let solver = solver.with_temperature(0.5);
+        assert_eq!(solver.weights(), vec![16.0, 1.0]);
+
+        let solver = solver.with_temperature(0.0);
+        assert_eq!(
+            solver.generate(2, 2, 0),
+            Err(WfcError::InvalidTemperature { temperature: 0.0 })
+        );
+    }
+
+    #[test]
+    fn generate_wrapping() {
+        // [0, 1, 2]
+        // [3, 4, 5]
+        // [6, 7, 8]
+        //
+        // Every pattern has a single neighbor in each direction, so outputs
+        // repeat every 3 pixels, and can only wrap around if their size is a
+        // multiple of 3.
+        let texture = img(3);
+        let patterns = crate::get_patterns(&texture, 2, true);
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+        assert!(solver.generate(4, 4, 0).is_ok());
+
+        let solver = solver.with_wrap(Wrap::Horizontal);
+        assert!(solver.generate(4, 3, 0).is_err());
+        assert!(solver.generate(6, 4, 0).is_ok());
+
+        let solver = solver.with_wrap(Wrap::Vertical);
+        assert!(solver.generate(3, 4, 0).is_err());
+        assert!(solver.generate(4, 6, 0).is_ok());
+
+        let solver = solver.with_wrap(Wrap::Both);
+        assert!(solver.generate(6, 4, 0).is_err());
+        assert!(solver.generate(6, 3, 0).is_ok());
+
+        let output = solver.generate(6, 3, 0).unwrap();
+        for (x, y) in (0..6).cartesian_product(0..3) {
+            assert_eq!(output[(x, y)], output[((x + 3) % 6, y)]);
+        }
+    }
+
+    #[test]
+    fn generate_values() {
+        // Any kind of value makes up patterns, not only pixels.
+        let texture = Texture::from_rows(
+            ["~~~..", "~~...", "~..##", "...##"]
+                .map(|row| row.chars().collect())
+                .to_vec(),
+        )
+        .unwrap();
+        let patterns = crate::get_patterns(&texture, 2, true);
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+
+        let output = solver.generate(7, 5, 0).unwrap();
+        assert_eq!(output.dimensions(), (7, 5));
+        for (x, y) in (0..6).cartesian_product(0..4) {
+            let window = super::Pattern::new(0, 2, &output, (x, y));
+            assert!(patterns.contains(&window), "{window}");
+        }
+    }
+
+    #[test]
+    fn generate_cube() {
+        // Lone dots, which must stay apart across the edges of the cube as
+        // well, however the faces turn.
+        let mut texture = Texture::from_fn(6, 6, |_, _| 1);
+        texture[(1, 1)] = 0;
+        texture[(4, 3)] = 0;
+        let patterns = crate::augment(crate::get_patterns(&texture, 3, true), 8).unwrap();
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+
+        let cube = CubeSphere::new(5);
+        let mut dots = 0;
+        for seed in 0..4 {
+            let output = solver.generate_cube(5, seed).unwrap();
+            assert_eq!(output, solver.generate_cube(5, seed).unwrap());
+            assert_eq!(output.size(), 5);
+
+            let is_dot = |idx: usize| {
+                let (face, (x, y)) = cube.idx_to_pos(idx);
+                output.face(face)[(x, y)] == 0
+            };
+            for idx in (0..cube.len()).filter(|&idx| is_dot(idx)) {
+                dots += 1;
+                assert!(cube.neighbors(idx).iter().all(|&(_, n)| !is_dot(n)));
+            }
+
+            let cross = output.to_cross(2);
+            assert_eq!(cross.dimensions(), (20, 15));
+            assert_eq!(cross[(5, 5)], output.face(Face::Front)[(0, 0)]);
+            assert_eq!(cross[(19, 9)], output.face(Face::Back)[(4, 4)]);
+            assert_eq!(cross[(0, 0)], 2);
+        }
+        assert!(dots > 0);
+
+        // A symmetry of `4` turns each pattern once but not twice, which
+        // isn't enough to put a lopsided shape across every edge.
+        let mut texture = Texture::from_fn(6, 6, |_, _| 1);
+        for pos in [(1, 1), (2, 1), (3, 1), (1, 2)] {
+            texture[pos] = 0;
+        }
+        let patterns = crate::augment(crate::get_patterns(&texture, 3, true), 4).unwrap();
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+        assert!(matches!(
+            solver.generate_cube(5, 0),
+            Err(WfcError::MissingRotation { .. })
+        ));
+        let patterns = crate::augment(crate::get_patterns(&texture, 3, true), 8).unwrap();
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+        assert!(!matches!(
+            solver.generate_cube(5, 0),
+            Err(WfcError::MissingRotation { .. })
+        ));
+
+        // A checkerboard can't go around the corners of a cube, where three
+        // faces meet.
+        let texture = Texture::from_rows(vec![vec![true, false], vec![false, true]]).unwrap();
+        let patterns = crate::augment(crate::get_patterns(&texture, 3, true), 8).unwrap();
+        let solver = super::Wfc::new(patterns.iter().collect_vec());
+        assert!(solver.generate(4, 4, 0).is_ok());
+        assert!(matches!(
+            solver.generate_cube(4, 0),
+            Err(WfcError::NodeContradiction { .. })
+        ));
+        assert_eq!(
+            solver.generate_cube(0, 0),
+            Err(WfcError::ZeroOutputSize {
+                width: 0,
+                height: 0
+            })
+        );
     }
 }